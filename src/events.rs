@@ -0,0 +1,178 @@
+//NEP-297 events. Every state change is logged as one line:
+//  EVENT_JSON:{"standard":"choice","version":"1.1.0","event":"vote_cast","data":[{...}]}
+//  Version changes when data of any event changes, so indexers can rebuild state from logs only
+use near_sdk::env;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json;
+use near_sdk::AccountId;
+
+use crate::{ScoringRule, VoteType};
+
+pub const EVENT_STANDARD: &str = "choice";
+pub const EVENT_VERSION: &str = "1.1.0";
+
+#[derive(Serialize, Debug)]
+#[serde(crate="near_sdk::serde")]
+#[serde(tag="event", content="data", rename_all="snake_case")]
+pub enum Event {
+    MembershipCreated(Vec<MembershipData>),
+    ProposalCreated(Vec<ProposalCreatedData>),
+    FundsChanged(Vec<FundsChangedData>),
+    ProposalCancelled(Vec<ProposalCancelledData>),
+    DecisionSubmitted(Vec<DecisionData>),
+    DecisionEdited(Vec<DecisionData>),
+    DecisionWithdrawn(Vec<DecisionWithdrawnData>),
+    ElectionStarted(Vec<ElectionStartedData>),
+    VoteCast(Vec<VoteCastData>),
+    TallyUpdated(Vec<TallyUpdatedData>),
+    ProposalSettled(Vec<ProposalSettledData>),
+    RewardCredited(Vec<PayoutData>),
+    PayoutSent(Vec<PayoutData>),
+    PayoutFailed(Vec<PayoutData>),
+    BondReleased(Vec<BondReleasedData>),
+}
+impl Event {
+    pub fn emit(self) {
+        env::log_str(&format!("EVENT_JSON:{}", self.to_json()));
+    }
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&EventLog {
+            standard: EVENT_STANDARD,
+            version: EVENT_VERSION,
+            event: self
+        })
+        .unwrap()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate="near_sdk::serde")]
+struct EventLog<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event: &'a Event
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate="near_sdk::serde")]
+pub struct MembershipData {
+    pub account_id: AccountId
+}
+#[derive(Serialize, Debug)]
+#[serde(crate="near_sdk::serde")]
+pub struct ProposalCreatedData {
+    pub proposal_id: U64,
+    pub owner: AccountId,
+    pub title: String,
+    pub metadata: String,
+    pub vote_type: VoteType,
+    pub scoring_rule: ScoringRule,
+    pub funds: U128,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub token_id: Option<AccountId>,   //NEP-141 token of funds, absent for Ⓝ
+    pub bond: U128,
+    pub max_decisions: u16,
+    pub winners: u16,
+    pub start_time: U64,
+    pub proposal_deadline: U64,
+    pub vote_deadline: U64
+}
+#[derive(Serialize, Debug)]
+#[serde(crate="near_sdk::serde")]
+pub struct FundsChangedData {
+    pub proposal_id: U64,
+    pub old_funds: U128,
+    pub new_funds: U128
+}
+//reason: "creator", "no_decisions", "no_votes" or "migration". Funds are refunded to owner
+#[derive(Serialize, Debug)]
+#[serde(crate="near_sdk::serde")]
+pub struct ProposalCancelledData {
+    pub proposal_id: U64,
+    pub reason: &'static str,
+    pub refunded: U128
+}
+#[derive(Serialize, Debug)]
+#[serde(crate="near_sdk::serde")]
+pub struct DecisionData {
+    pub proposal_id: U64,
+    pub performer: AccountId,
+    pub metadata: String,
+    pub budget: Option<U128>
+}
+#[derive(Serialize, Debug)]
+#[serde(crate="near_sdk::serde")]
+pub struct DecisionWithdrawnData {
+    pub proposal_id: U64,
+    pub performer: AccountId
+}
+//Stage changes by block time, so election start is logged with the first ballot
+#[derive(Serialize, Debug)]
+#[serde(crate="near_sdk::serde")]
+pub struct ElectionStartedData {
+    pub proposal_id: U64,
+    pub candidates: Vec<AccountId>
+}
+#[derive(Serialize, Debug)]
+#[serde(crate="near_sdk::serde")]
+pub struct VoteCastData {
+    pub proposal_id: U64,
+    pub voter: AccountId,
+    pub ranking: Vec<Vec<AccountId>>
+}
+//Ballots counted by tally_step so far
+#[derive(Serialize, Debug)]
+#[serde(crate="near_sdk::serde")]
+pub struct TallyUpdatedData {
+    pub proposal_id: U64,
+    pub counted: U64,
+    pub total: U64
+}
+//paid - sum of all performer shares, returned - unallocated funds and rounding dust sent back to owner
+#[derive(Serialize, Debug)]
+#[serde(crate="near_sdk::serde")]
+pub struct ProposalSettledData {
+    pub proposal_id: U64,
+    pub paid: U128,
+    pub returned: U128
+}
+//reward_credited - settlement added amount to claimable balance of receiver,
+//payout_sent - claimed amount is delivered, payout_failed - claim transfer failed and amount is claimable again
+#[derive(Serialize, Debug)]
+#[serde(crate="near_sdk::serde")]
+pub struct PayoutData {
+    pub proposal_id: U64,
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub token_id: Option<AccountId>
+}
+//Slashed bond is split equally between performers, remainder goes to owner
+#[derive(Serialize, Debug)]
+#[serde(crate="near_sdk::serde")]
+pub struct BondReleasedData {
+    pub proposal_id: U64,
+    pub owner: AccountId,
+    pub amount: U128,
+    pub slashed: bool
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_format() {
+        let event = Event::FundsChanged(vec![FundsChangedData {
+            proposal_id: U64(1),
+            old_funds: U128(10),
+            new_funds: U128(20)
+        }]);
+        assert_eq!(
+            event.to_json(),
+            r#"{"standard":"choice","version":"1.1.0","event":"funds_changed","data":[{"proposal_id":"1","old_funds":"10","new_funds":"20"}]}"#
+        );
+    }
+}
@@ -0,0 +1,101 @@
+//NEP-141 prize pools. Creator sends tokens with `ft_transfer_call` to this contract and `msg`:
+//  {"action": "create_proposal", "vote_type": {"type": "ProjectElection"}, "title": ..., "max_decisions": 3, ...}
+//  {"action": "top_up", "proposal_id": 1}
+//  Any contract can call ft_on_transfer with any sender_id, so only tokens accepted by contract account
+//  can fund proposals: their sender_id is the real owner of transferred tokens.
+//  ft_transfer_call can't carry Ⓝ, so creator bond of token proposal is taken from creator's storage deposit.
+//  Rewards are credited in proposal token and claimed with `claim_for`, which calls `ft_transfer`
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Serialize, Deserialize};
+use near_sdk::serde_json;
+use near_sdk::{env, ext_contract, near_bindgen, AccountId, Gas, PromiseOrValue};
+
+use crate::*;
+
+pub const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);   //10 TGas
+
+#[ext_contract(ext_ft)]
+pub trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate="near_sdk::serde")]
+#[serde(tag="action", rename_all="snake_case")]
+pub enum FtTransferMsg {
+    CreateProposal(ProposalParams),
+    TopUp { proposal_id: ProposalId },
+}
+
+#[near_bindgen]
+impl Contract {
+    //Caller is the token contract. On panic all tokens go back to sender, otherwise all of them are used
+    pub fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128> {
+        let token_id = env::predecessor_account_id();
+        assert!(self.accepted_tokens.contains(&token_id), "Token @{} is not accepted for prize pools", token_id);
+        assert!(amount.0 > 0, "Amount of @{} must be greater than zero", token_id);
+        let message: FtTransferMsg = serde_json::from_str(&msg)
+            .expect(&(format!("Invalid msg: {}. Expected create_proposal or top_up action", msg)));
+
+        match message {
+            FtTransferMsg::CreateProposal(params) => {
+                self.take_storage_deposit(&sender_id, CREATOR_BOND);
+                self.add_proposal(sender_id, Some(token_id), amount.0, CREATOR_BOND, params);
+            }
+            FtTransferMsg::TopUp { proposal_id } => {
+                let mut proposal = self.proposals
+                    .get(&proposal_id)
+                    .expect(&(format!("No proposal with that id {}",proposal_id)));
+                assert!(
+                    proposal.token_id.as_ref() == Some(&token_id),
+                    "Proposal {} is not funded with @{}", proposal_id, token_id
+                );
+                assert!(proposal.current_status() == ProposalStatus::Open, "Proposal must be in Open status for changing funds");
+                assert!(proposal.owner == sender_id, "Only proposal creator can change funds");
+
+                let old_funds = proposal.funds;
+                proposal.funds += amount.0;
+                self.proposals.insert(&proposal_id, &proposal);
+                Event::FundsChanged(vec![FundsChangedData {
+                    proposal_id: U64(proposal_id),
+                    old_funds: U128(old_funds),
+                    new_funds: U128(proposal.funds)
+                }]).emit();
+            }
+        }
+        self.assert_escrow();
+        PromiseOrValue::Value(U128(0))
+    }
+    //Accepted tokens are managed by contract account, like state migration
+    #[private]
+    pub fn add_accepted_token(&mut self, token_id: AccountId) {
+        self.accepted_tokens.insert(&token_id);
+    }
+    //Proposals which are already funded with removed token are settled and claimed as usual
+    #[private]
+    pub fn remove_accepted_token(&mut self, token_id: AccountId) {
+        self.accepted_tokens.remove(&token_id);
+    }
+    pub fn view_accepted_tokens(&self) -> Vec<AccountId> {
+        self.accepted_tokens.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_msg() {
+        let msg = r#"{"action":"create_proposal","vote_type":{"type":"ProjectElection"},"title":"create logo",
+            "max_decisions":3,"proposal_duration":1000,"vote_duration":1000,"metadata":"we need logo for us"}"#;
+        match serde_json::from_str::<FtTransferMsg>(msg).unwrap() {
+            FtTransferMsg::CreateProposal(params) => assert_eq!(params.title, "create logo".to_string()),
+            message => panic!("Unexpected message {:?}", message)
+        }
+        assert_eq!(
+            serde_json::from_str::<FtTransferMsg>(r#"{"action":"top_up","proposal_id":1}"#).unwrap(),
+            FtTransferMsg::TopUp { proposal_id: 1 }
+        );
+    }
+}
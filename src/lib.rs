@@ -1,69 +1,318 @@
-use num_traits::pow;
-use std::collections::HashMap;
-use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::{env,near_bindgen, AccountId, Balance, Duration, Timestamp, Promise, PromiseResult, Gas};
+use near_sdk::{env, ext_contract, near_bindgen, AccountId, Balance, Duration, Timestamp, Promise, PromiseResult, Gas};
 use near_sdk::serde::{Serialize, Deserialize};
-use near_sdk::collections::{LookupMap, UnorderedMap};
+use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
+use near_sdk::json_types::{U128, U64};
+
+mod events;
+mod fungible_token;
+mod storage;
+mod vote_engine;
+use events::*;
+pub use fungible_token::FtTransferMsg;
+use fungible_token::{ext_ft, GAS_FOR_FT_TRANSFER};
+pub use storage::{StorageBalance, StorageBalanceBounds};
+use storage::StorageAccount;
+pub use vote_engine::ScoringRule;
+use vote_engine::{split_funds, pairwise_preferences, schulze_paths, schulze_winners, Tally};
 
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 const MAX_TITLE_SIZE: usize = 20;
 const MAX_METADATA_SIZE: usize = 150;
-const RESERVED_FUNDS: Balance = 100_000_000_000_000_000_000_000;  //0,1Ⓝ
+const RESERVED_FUNDS: Balance = 100_000_000_000_000_000_000_000;  //0,1Ⓝ min storage deposit for membership
+const MIN_FUNDS: Balance = 10_000_000_000_000_000_000_000_000;    //10Ⓝ
 const CREATOR_BOND: Balance = 10_000_000_000_000_000_000_000_000; //10Ⓝ
+const SETTLE_GRACE_PERIOD: Duration = 604_800_000_000_000;        //7 days after vote deadline
+const MIGRATED_PROPOSAL_DURATION: Duration = 259_200_000_000_000; //3 days from start for proposals created without deadlines
+const MIGRATED_VOTE_DURATION: Duration = 86_400_000_000_000;      //1 day
+const MIN_DECISIONS: usize = 2;   //quorum of decisions for election
+const MAX_DECISIONS: usize = 100; //keeps weights, pairwise matrix and settlement of one proposal within gas limit
+const MAX_PERFORMER_DECISIONS: usize = 50;  //Schulze paths are O(n³) in settle, pairwise matrix n² is stored by every tally step
+const MIN_VOTES: usize = 1;       //quorum of votes for payout
+const MAX_PAGE_LIMIT: u64 = 100;  //proposals returned by one view call
+const SETTLE_TALLY_LIMIT: u64 = 50;   //ballots which settle can count itself, larger proposals need tally_step
+const GAS_FOR_CLAIM_CALLBACK: Gas = Gas(10_000_000_000_000);   //10 TGas
+const GAS_FOR_CLAIMED_REWARD: Gas = Gas(1_000_000_000_000);    //1 TGas more in callback for every claimed proposal
+
+type ProposalId = u64;
 
-type ProposalId = String;
+#[ext_contract(ext_self)]
+trait ClaimCallbacks {
+    fn on_claim(&mut self, account_id: AccountId, token_id: Option<AccountId>, rewards: Vec<(ProposalId, U128)>);
+}
 
 #[allow(dead_code)]
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+#[derive(BorshSerialize, BorshDeserialize)]
 //Proposal. Every registered user can create this contest. Other users(choicers) can submit their decisions and 
 //  when voting starts they can vote for them. When election stage will be finished they get raised proposal funds
 pub struct Proposal {
     status : ProposalStatus,
     vote_type : VoteType,
+    scoring_rule : ScoringRule,
     id : ProposalId,
     title : String,
-    funds : Balance,                 //Ⓝ attached to proposal
+    funds : Balance,                 //Ⓝ attached to proposal, or amount of token_id
+    token_id : Option<AccountId>,    //NEP-141 token of funds. None for Ⓝ
+    bond : Balance,                  //Ⓝ creator bond. Held separately from funds
     owner : AccountId,
     metadata : String,               //description details
     max_decisions : u16,
-    decisions : Vec<Decision>,
-    vote_results : Vec<Votes>,
+    winners : u16,                   //number of Schulze winners for PerformerElection
+    decisions : UnorderedMap<AccountId, Decision>,  //by performer. Stored apart from proposal under prefix of proposal id
+    ballots : UnorderedMap<AccountId, Votes>,       //by voter. Stored apart from proposal under prefix of proposal id
     start_time: Timestamp,
-    //proposal_duration : Duration,  //TODO: proposal creator can set estimated time values for submitting decisions 
-    //vote_duration : Duration,      //TODO: proposal creator can set estimated time values for submittting votes       
+    proposal_deadline : Timestamp,   //decisions are accepted until this moment
+    vote_deadline : Timestamp,       //votes are accepted until this moment
+    settled : bool,                  //funds are already dispersed
+}
+impl Proposal {
+    //Every proposal keeps decisions and ballots in own collections, so a new decision or ballot
+    //  writes only itself and does not re-serialize the others
+    fn new_collections(proposal_id: ProposalId) -> (UnorderedMap<AccountId, Decision>, UnorderedMap<AccountId, Votes>) {
+        (
+            UnorderedMap::new([b"decisions".as_slice(), &proposal_id.to_le_bytes()].concat()),
+            UnorderedMap::new([b"ballots".as_slice(), &proposal_id.to_le_bytes()].concat())
+        )
+    }
+    //Decisions in stored order. Ballots rank decisions by index in this list. Withdrawn decision
+    //  takes place of the last one, but it is possible only while Open, before any ballot
+    fn decision_list(&self) -> Vec<Decision> {
+        self.decisions.values_as_vector().to_vec()
+    }
+    //Stored status is updated lazily, so the real stage is always derived from the block time:
+    //  [start_time .. proposal_deadline) - Open
+    //  [proposal_deadline .. vote_deadline) - Vote
+    //  [vote_deadline .. ) - Payout
+    fn current_status(&self) -> ProposalStatus {
+        if self.status == ProposalStatus::Payout || self.status == ProposalStatus::Cancelled {
+            return self.status.clone();
+        }
+        let now = env::block_timestamp();
+        if now >= self.vote_deadline {
+            ProposalStatus::Payout
+        } else if now >= self.proposal_deadline {
+            ProposalStatus::Vote
+        } else {
+            ProposalStatus::Open
+        }
+    }
 }
 #[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
 pub struct Decision {
     performer : AccountId,
-    metadata : String
+    metadata : String,
+    budget : Option<Balance>         //ProjectElection: requested budget. Project never gets more than requested
 }
 //It's not like (Yes/No) votes. You must ordering users from best to worst like:
 //  1st place  - "account_1.near"
 //  2nd place  - "account_2.near"
 //  ...
 //  last place - "account_last.near"
-#[derive(BorshDeserialize, BorshSerialize, Debug, Serialize, Deserialize, Clone)]
+//Stored compactly as decision indexes grouped by place. Group with several decisions is a tie
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
 pub struct Votes {
     from: AccountId,
-    vote: HashMap<String, f64>
+    ranking: Vec<Vec<u16>>
+}
+impl Votes {
+    //Standard competition places ("1224"): tied decisions share the best place of their group
+    fn places(&self) -> Vec<(u16, usize)> {
+        let mut places = Vec::new();
+        let mut place = 1;
+        for group in self.ranking.iter() {
+            for index in group.iter() {
+                places.push((*index, place));
+            }
+            place += group.len();
+        }
+        places
+    }
+}
+//Ballot for `vote_ranked`. Accounts are ordered from best to worst:
+//  ["account_1.near", "account_2.near", ...]
+//  or with explicit ties: [["account_1.near"], ["account_2.near", "account_3.near"], ...]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate="near_sdk::serde")]
+#[serde(untagged)]
+pub enum RankedBallot {
+    Ranking(Vec<AccountId>),
+    Tiers(Vec<Vec<AccountId>>),
+}
+impl RankedBallot {
+    fn into_tiers(self) -> Vec<Vec<AccountId>> {
+        match self {
+            RankedBallot::Ranking(ranking) => ranking.into_iter().map(|account| vec![account]).collect(),
+            RankedBallot::Tiers(tiers) => tiers,
+        }
+    }
+}
+//Proposal settings chosen by creator. Passed to `create_proposal` with Ⓝ funds
+//  or in `ft_transfer_call` msg to create token proposal
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate="near_sdk::serde")]
+pub struct ProposalParams {
+    vote_type : VoteType,
+    title : String,
+    max_decisions : u16,
+    proposal_duration : Duration,
+    vote_duration : Duration,
+    metadata : String,
+    scoring_rule : Option<ScoringRule>,
+    winners : Option<u16>,
+}
+//JSON views for clients. Balances and timestamps are strings, because JS numbers lose precision
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate="near_sdk::serde")]
+pub struct DecisionView {
+    performer: AccountId,
+    metadata: String,
+    budget: Option<U128>
+}
+impl From<&Decision> for DecisionView {
+    fn from(decision: &Decision) -> Self {
+        DecisionView {
+            performer: decision.performer.clone(),
+            metadata: decision.metadata.clone(),
+            budget: decision.budget.map(U128)
+        }
+    }
+}
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate="near_sdk::serde")]
+pub struct ChoicerView {
+    account_id: AccountId,
+    total_received: U128,
+    completed_choices: u16,
+    current_choices: u16,
+    proposals_created: u16,
+    total_spending: U128
+}
+impl From<&Choicer> for ChoicerView {
+    fn from(choicer: &Choicer) -> Self {
+        ChoicerView {
+            account_id: choicer.account_id.clone(),
+            total_received: U128(choicer.total_received),
+            completed_choices: choicer.completed_choices,
+            current_choices: choicer.current_choices,
+            proposals_created: choicer.proposals_created,
+            total_spending: U128(choicer.total_spending)
+        }
+    }
+}
+//Proposal for JSON clients. Ballots are shown by count, see `view_vote_board` for ballots.
+//  Decisions are shown only by `view_proposal`, paged lists skip them to stay within view gas
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate="near_sdk::serde")]
+pub struct ProposalView {
+    id: ProposalId,
+    title: String,
+    owner: AccountId,
+    metadata: String,
+    status: ProposalStatus,          //current stage, not the stored one
+    vote_type: VoteType,
+    scoring_rule: ScoringRule,
+    funds: U128,
+    token_id: Option<AccountId>,     //NEP-141 token of funds and rewards. None for Ⓝ
+    bond: U128,
+    max_decisions: u16,
+    winners: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decisions: Option<Vec<DecisionView>>,
+    votes: u32,
+    start_time: U64,
+    proposal_deadline: U64,
+    vote_deadline: U64,
+    settled: bool
+}
+impl From<&Proposal> for ProposalView {
+    fn from(proposal: &Proposal) -> Self {
+        ProposalView {
+            id: proposal.id,
+            title: proposal.title.clone(),
+            owner: proposal.owner.clone(),
+            metadata: proposal.metadata.clone(),
+            status: proposal.current_status(),
+            vote_type: proposal.vote_type.clone(),
+            scoring_rule: proposal.scoring_rule.clone(),
+            funds: U128(proposal.funds),
+            token_id: proposal.token_id.clone(),
+            bond: U128(proposal.bond),
+            max_decisions: proposal.max_decisions,
+            winners: proposal.winners,
+            decisions: None,
+            votes: proposal.ballots.len() as u32,
+            start_time: U64(proposal.start_time),
+            proposal_deadline: U64(proposal.proposal_deadline),
+            vote_deadline: U64(proposal.vote_deadline),
+            settled: proposal.settled
+        }
+    }
+}
+//Vote board entry with accounts instead of decision indexes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate="near_sdk::serde")]
+pub struct VotesView {
+    from: AccountId,
+    ranking: Vec<Vec<AccountId>>
 }
+//Audit data for PerformerElection: preferences[i][j] - ballots ranking candidate i above candidate j
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate="near_sdk::serde")]
+pub struct PairwiseView {
+    candidates: Vec<AccountId>,
+    preferences: Vec<Vec<u32>>,
+    strongest_paths: Vec<Vec<u32>>,
+    winners: Vec<AccountId>
+}
+//Reasons to reject a ballot in `vote`
+#[derive(Debug, PartialEq)]
+pub enum BallotError {
+    NotPerformer(AccountId),
+    AlreadyVoted(AccountId),
+    SelfRanking(AccountId),
+    UnknownCandidate(String),
+    DuplicateCandidate(AccountId),
+    MissingCandidate(AccountId),
+    EmptyTier,
+    InvalidPlace(String, f64),
+    DuplicatePlace(f64),
+}
+impl fmt::Display for BallotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BallotError::NotPerformer(account) => write!(f, "Only decision performers can vote. @{} has no decision in proposal", account),
+            BallotError::AlreadyVoted(account) => write!(f, "@{} already voted for this proposal", account),
+            BallotError::SelfRanking(account) => write!(f, "@{} cannot rank own decision", account),
+            BallotError::UnknownCandidate(account) => write!(f, "@{} has no decision in proposal", account),
+            BallotError::DuplicateCandidate(account) => write!(f, "@{} is ranked more than once", account),
+            BallotError::MissingCandidate(account) => write!(f, "Ballot must rank every other performer. @{} is missing", account),
+            BallotError::EmptyTier => write!(f, "Ballot cannot contain empty tie groups"),
+            BallotError::InvalidPlace(account, place) => write!(f, "Invalid place {} for @{}. Places must be integers from 1 to number of other performers", place, account),
+            BallotError::DuplicatePlace(place) => write!(f, "Place {} is given more than once", place),
+        }
+    }
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(crate="near_sdk::serde")]
 #[serde(tag="type")]
 pub enum ProposalStatus {
     Open,
     Vote,
-    Payout
+    Payout,
+    Cancelled                        //closed without payout: cancelled by creator or no quorum
 }
-#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone)]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(crate="near_sdk::serde")]
 #[serde(tag="type")]
-//TODO: proposals will be not only for contests votes. DAO's can made Project Election vote, which allows
-//      disperse pool funds for investments proportionally election decisions 
+//PerformerElection - contest, Schulze winners take proposal funds
+//ProjectElection   - DAO's disperse pool funds for investments proportionally election decisions.
+//                    Decisions are project applications which can request a budget
 pub enum VoteType {
     PerformerElection,
     ProjectElection
@@ -78,22 +327,63 @@ pub struct Choicer {
     proposals_created: u16,
     total_spending: Balance      //Ⓝ spended as proposal creator
 }
-//Accumulate votes and multisend proposal funds to every submitted participant proportionally to votes for them
-#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Clone)]
-struct VoteEngine {
-    weights:Vec<f64>,
-    results:Vec<HashMap<String, f64>>,
-}
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct Contract {
     proposals : UnorderedMap<ProposalId, Proposal>,      
+    last_proposal_id : ProposalId,                       //every new proposal takes next id
+    total_locked : Balance,                              //Ⓝ held in escrow for all unsettled proposals
     choicers   : LookupMap<AccountId, Choicer>,
-    vote_engine : VoteEngine                             
+    storage_accounts : LookupMap<AccountId, StorageAccount>,  //NEP-145 storage deposits
+    claimable : LookupMap<AccountId, Balance>,           //Ⓝ credited by settlements and not claimed yet. Part of total_locked
+    rewards : LookupMap<AccountId, Vec<(ProposalId, Balance)>>,  //claimable Ⓝ by proposal
+    token_rewards : LookupMap<AccountId, Vec<(ProposalId, Balance)>>,  //claimable tokens by proposal, in token of proposal
+    tallies : LookupMap<ProposalId, Tally>,              //vote counting in progress
+    accepted_tokens : UnorderedSet<AccountId>,           //NEP-141 contracts which can fund proposals
 }
 //impl Default panic -todo
 
+//Layout before sequential ids: proposals were keyed by `owner + "001"` string.
+//  There were no deadlines, stages were switched by hand and no proposal was paid out
+#[derive(BorshDeserialize, BorshSerialize)]
+struct OldProposal {
+    status : ProposalStatus,
+    vote_type : VoteType,
+    id : String,
+    title : String,
+    funds : Balance,
+    owner : AccountId,
+    metadata : String,
+    max_decisions : u16,
+    decisions : Vec<OldDecision>,
+    vote_results : Vec<OldVotes>,
+    start_time: Timestamp,
+}
+#[derive(BorshDeserialize, BorshSerialize)]
+struct OldDecision {
+    performer : AccountId,
+    metadata : String
+}
+//Ballot with f64 places: {"account_1.near": 1.0, ...}
+#[derive(BorshDeserialize, BorshSerialize)]
+struct OldVotes {
+    from: AccountId,
+    vote: HashMap<String, f64>
+}
+//Vote engine scratch data which was persisted in contract state
+#[derive(BorshDeserialize, BorshSerialize)]
+struct OldVoteEngine {
+    weights:Vec<f64>,
+    results:Vec<HashMap<String, f64>>,
+}
+#[derive(BorshDeserialize, BorshSerialize)]
+struct OldContract {
+    proposals : UnorderedMap<String, OldProposal>,
+    choicers   : LookupMap<AccountId, Choicer>,
+    vote_engine : OldVoteEngine
+}
+
 #[near_bindgen]
 impl Contract {
     #[init]
@@ -104,196 +394,359 @@ impl Contract {
         );
 
         Self {
-            proposals: UnorderedMap::new(b"proposals".to_vec()),
+            proposals: UnorderedMap::new(b"proposals_by_id".to_vec()),
+            last_proposal_id: 0,
+            total_locked: 0,
             choicers: LookupMap::new(b"choicers".to_vec()),
-            vote_engine : VoteEngine { 
-                weights: Vec::new(),
-                results: Vec::new()
+            storage_accounts: LookupMap::new(b"storage_accounts".to_vec()),
+            claimable: LookupMap::new(b"claimable".to_vec()),
+            rewards: LookupMap::new(b"rewards".to_vec()),
+            token_rewards: LookupMap::new(b"token_rewards".to_vec()),
+            tallies: LookupMap::new(b"tallies".to_vec()),
+            accepted_tokens: UnorderedSet::new(b"accepted_tokens".to_vec()),
+        }
+    }
+    //Re-key proposals stored with string ids. Ids are given in storage order.
+    //  Decisions and ballots embedded into proposals are moved to per-proposal collections
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Only contract account can migrate state"
+        );
+        let mut old: OldContract = env::state_read().expect("No state to migrate");
+
+        let mut proposals: UnorderedMap<ProposalId, Proposal> = UnorderedMap::new(b"proposals_by_id".to_vec());
+        let mut last_proposal_id: ProposalId = 0;
+        let mut total_locked: Balance = 0;
+        let mut oversized: Vec<ProposalId> = Vec::new();
+        for (_, old_proposal) in old.proposals.iter() {
+            last_proposal_id += 1;
+            total_locked += old_proposal.funds;
+            //deadlines are counted from start, so long-running proposals can be settled or refunded at once
+            let proposal_deadline = old_proposal.start_time + MIGRATED_PROPOSAL_DURATION;
+            let vote_deadline = proposal_deadline + MIGRATED_VOTE_DURATION;
+            //decisions embedded into proposal are moved to own collection. Old layout allowed
+            //  several decisions of one performer: only the first is kept and counted in choices
+            let (mut decisions, ballots) = Proposal::new_collections(last_proposal_id);
+            for decision in old_proposal.decisions.into_iter() {
+                if decisions.get(&decision.performer).is_some() {
+                    let mut choicer = old.choicers
+                        .get(&decision.performer)
+                        .expect(&(format!("No choicer with id @{}",decision.performer)));
+                    choicer.current_choices = choicer.current_choices.saturating_sub(1);
+                    old.choicers.insert(&decision.performer, &choicer);
+                    continue;
+                }
+                decisions.insert(&decision.performer, &Decision {
+                    performer: decision.performer.clone(),
+                    metadata: decision.metadata,
+                    budget: None
+                });
             }
+            let decisions_limit = match old_proposal.vote_type {
+                VoteType::PerformerElection => MAX_PERFORMER_DECISIONS,
+                VoteType::ProjectElection => MAX_DECISIONS
+            };
+            let mut proposal = Proposal {
+                status : old_proposal.status,
+                vote_type : old_proposal.vote_type,
+                scoring_rule : ScoringRule::Geometric,
+                id : last_proposal_id,
+                title : old_proposal.title,
+                funds : old_proposal.funds,
+                token_id : None,
+                bond : 0,
+                owner : old_proposal.owner,
+                metadata : old_proposal.metadata,
+                max_decisions : old_proposal.max_decisions.min(decisions_limit as u16),
+                winners : 1,
+                decisions,
+                ballots,
+                start_time : old_proposal.start_time,
+                proposal_deadline,
+                vote_deadline,
+                settled : false
+            };
+            //ballots are checked by the same rules as new ones, ballots which can't be counted are dropped
+            let decision_list = proposal.decision_list();
+            for votes in old_proposal.vote_results.iter() {
+                let checked = validate_places(&proposal, &votes.vote)
+                    .and_then(|_| tiers_from_places(&votes.vote))
+                    .and_then(|tiers| validate_ranking(&proposal, &decision_list, &votes.from, &tiers).map(|_| tiers));
+                match checked {
+                    Ok(tiers) => {
+                        proposal.ballots.insert(&votes.from, &Votes {
+                            from: votes.from.clone(),
+                            ranking: index_tiers(&decision_list, &tiers)
+                        });
+                    }
+                    Err(error) => env::log_str(&(format!(
+                        "Ballot of @{} for proposal {} dropped: {}", votes.from, old_proposal.id, error
+                    )))
+                }
+            }
+            //too many decisions to settle within gas limit
+            if decision_list.len() > decisions_limit {
+                oversized.push(last_proposal_id);
+            }
+            env::log_str(&(format!("Proposal {} migrated to id {}", old_proposal.id, last_proposal_id)));
+            proposals.insert(&last_proposal_id, &proposal);
+        }
+        old.proposals.clear();
+
+        let mut contract = Self {
+            proposals,
+            last_proposal_id,
+            total_locked,
+            choicers: old.choicers,
+            storage_accounts: LookupMap::new(b"storage_accounts".to_vec()),
+            claimable: LookupMap::new(b"claimable".to_vec()),
+            rewards: LookupMap::new(b"rewards".to_vec()),
+            token_rewards: LookupMap::new(b"token_rewards".to_vec()),
+            tallies: LookupMap::new(b"tallies".to_vec()),
+            accepted_tokens: UnorderedSet::new(b"accepted_tokens".to_vec()),
+        };
+        //proposals which can't be settled are closed and funds are refunded to creators
+        for proposal_id in oversized {
+            let mut proposal = contract.proposals.get(&proposal_id).unwrap();
+            contract.close_without_payout(&mut proposal, "migration", false);
         }
+        contract.assert_escrow();
+        contract
     }
     //CREATOR SIDE
     #[payable]
-    pub fn create_proposal(
-        &mut self,
-        vote_type : VoteType,
-        title : String,
-        funds : Balance,
-        max_decisions : u16,
-        //proposal_duration : Duration,
-        //vote_duration : Duration, 
-        metadata : String,
-	) {
-
+    pub fn create_proposal(&mut self, params: ProposalParams, funds: U128) -> ProposalId {
+        let funds = funds.0;
         let predecessor = env::predecessor_account_id();
-        assert!(
-            self.choicers.contains_key(&predecessor),
-            "You must create a membership first to push your proposals"
-        );
-
         let deposit = env::attached_deposit();
+        assert!(funds >= MIN_FUNDS, "Min deposit for proposal = {}Ⓝ", yton(MIN_FUNDS));
         assert!(
-            deposit >= CREATOR_BOND,
-            "You need have at least 10Ⓝ to create proposal" 
-        );
-
-        assert!(funds >= CREATOR_BOND, "Min deposit for proposal = {}Ⓝ", CREATOR_BOND);
-        assert!(
-            deposit > funds,
-            "You need fill your balance on {}Ⓝ 
-            to create proposal with funds value = {}Ⓝ", yton(funds - deposit), yton(funds) 
-        );
-
-        assert!(
-            title.len() <= MAX_TITLE_SIZE && metadata.len() <= MAX_METADATA_SIZE ,
-            "Too many symbols. Max title size is {} . Max description metadata size is {}", MAX_TITLE_SIZE, MAX_METADATA_SIZE
+            deposit >= funds + CREATOR_BOND,
+            "You need attach {} yoctoⓃ more 
+            to create proposal with funds value = {}Ⓝ and creator bond = {}Ⓝ",
+            funds + CREATOR_BOND - deposit, yton(funds), yton(CREATOR_BOND) 
         );
 
-        let performer = env::predecessor_account_id();
-        //let proposal_id = bs58::encode(env::sha256(&env::random_seed())).into_string(); 
-        let proposal_id = performer.to_string()+"001";                //for tests
-        let proposal = Proposal {
-            status : ProposalStatus::Open,
-            vote_type,
-            id : proposal_id.clone(),
-            title,
-            funds,
-            owner : performer,
-            metadata,
-            max_decisions,
-            decisions : Vec::new(),
-            vote_results : Vec::new(),
-            start_time: env::block_timestamp(),
-            //proposal_duration,
-            //vote_duration            
-        };
-	
-        env::log_str(&(format!("Wow! Created a new Proposal: id {} proposal {:#?}", &proposal_id, &proposal).to_string()));
-		
-        let mut choicer = self.choicers
-                .get(&predecessor)
-                .expect(&(format!("No choicer with id @{}",predecessor)));
-
-        choicer.current_choices += 1;
-        choicer.proposals_created += 1;
-        self.choicers.insert(&predecessor,&choicer);
-
-        self.proposals.insert(&proposal_id, &proposal);
+        let proposal_id = self.add_proposal(predecessor.clone(), None, funds, CREATOR_BOND, params);
+        //exact funds and bond are locked, surplus goes back to creator
+        refund(&predecessor, deposit - funds - CREATOR_BOND);
+        self.assert_escrow();
+        proposal_id
     }
 
     pub fn view_decisions(
         &self,
-        proposal_id: String
-    ) -> Vec<Decision> {
-        self.proposals
+        proposal_id: ProposalId
+    ) -> Vec<DecisionView> {
+        let proposal = self.proposals
             .get(&proposal_id)
-            .expect(&(format!("No proposal with id {}",&proposal_id)))
-            .decisions
+            .expect(&(format!("No proposal with id {}",&proposal_id)));
+        proposal.decisions
+            .values()
+            .map(|decision| DecisionView::from(&decision))
+            .collect()
     }
 
     #[payable]
+    //Raising funds requires attaching the difference, lowering them refunds it
     pub fn change_funds(
         &mut self,
-        proposal_id: String,
-        new_funds: f64
+        proposal_id: ProposalId,
+        new_funds: U128
     ) {
+        let new_funds = new_funds.0;
         let mut proposal = self.proposals
             .get(&proposal_id)
             .expect(&(format!("No proposal with that id {}",proposal_id)));
 
-        assert!(proposal.status == ProposalStatus::Open, "Proposal must be in Open status for changing funds");
+        assert!(proposal.current_status() == ProposalStatus::Open, "Proposal must be in Open status for changing funds");
+        assert!(
+            proposal.token_id.is_none(),
+            "Proposal {} is funded with tokens. Top it up with ft_transfer_call", proposal_id
+        );
 
         let owner = env::predecessor_account_id();
         assert!(
             proposal.owner == owner,
             "Only proposal creator can change funds"
         );
-        let old_funds = proposal.funds;
-        proposal.funds = ntoy(new_funds as Balance);
+        assert!(new_funds >= MIN_FUNDS, "Min deposit for proposal = {}Ⓝ", yton(MIN_FUNDS));
 
-        self.proposals.insert(&proposal_id,&proposal);
-        env::log_str(&(format!("Change funds from {}Ⓝ into {}Ⓝ  for proposal: {} ", yton(old_funds), yton(proposal.funds), proposal.title)));
-    }
-    //TODO: Made it actions when time = estimated_time for it (via Duration) 
-    fn start_election(&mut self, proposal_id: String) {
-        let mut proposal = self.proposals
-            .get(&proposal_id)
-            .expect(&(format!("No proposal with that id {}",&proposal_id)));
-        proposal.status = ProposalStatus::Vote;
+        let deposit = env::attached_deposit();
+        let old_funds = proposal.funds;
+        if new_funds >= old_funds {
+            let delta = new_funds - old_funds;
+            assert!(
+                deposit >= delta,
+                "You need attach {} yoctoⓃ to raise funds from {}Ⓝ to {}Ⓝ", delta, yton(old_funds), yton(new_funds)
+            );
+            self.total_locked += delta;
+            refund(&owner, deposit - delta);
+        } else {
+            let delta = old_funds - new_funds;
+            self.total_locked -= delta;
+            refund(&owner, deposit + delta);
+        }
+        proposal.funds = new_funds;
 
         self.proposals.insert(&proposal_id,&proposal);
+        self.assert_escrow();
+        Event::FundsChanged(vec![FundsChangedData {
+            proposal_id: U64(proposal_id),
+            old_funds: U128(old_funds),
+            new_funds: U128(new_funds)
+        }]).emit();
     }
-    fn finish_election(&mut self, proposal_id: String) {
+    //Creator can withdraw proposal while it is open. Funds are always refunded,
+    //  bond is slashed between participants if decisions were already submitted
+    pub fn cancel_proposal(&mut self, proposal_id: ProposalId) {
         let mut proposal = self.proposals
             .get(&proposal_id)
-            .expect(&(format!("No proposal with that id {}",&proposal_id)));
-        proposal.status = ProposalStatus::Payout;
-
-        self.proposals.insert(&proposal_id,&proposal);
-    } 
+            .expect(&(format!("No proposal with that id {}",proposal_id)));
 
-//CHOICER SIDE
-    #[payable]
-    pub fn create_membership(&mut self) {
         assert!(
-            env::attached_deposit() >= RESERVED_FUNDS,
-            "You need have at least 0.1Ⓝ to create membership"
+            proposal.owner == env::predecessor_account_id(),
+            "Only proposal creator can cancel proposal"
+        );
+        let status = proposal.current_status();
+        assert!(
+            status == ProposalStatus::Open,
+            "Proposal can be cancelled only while Open. Proposal status is {:?}", status
         );
 
-        let member_id = env::predecessor_account_id();
-
-        let member = Choicer {
-            account_id:member_id.clone(),
-            total_received:0,
-            completed_choices:0,
-            current_choices:0,
-            proposals_created: 0,
-            total_spending: 0 
-        };
+        let slash = !proposal.decisions.is_empty();
+        self.close_without_payout(&mut proposal, "creator", slash);
+    }
+    //Refund funds to creator, release bond and free everyone involved
+    fn close_without_payout(&mut self, proposal: &mut Proposal, reason: &'static str, slash_bond: bool) {
+        proposal.status = ProposalStatus::Cancelled;
+        proposal.settled = true;
+        self.proposals.insert(&proposal.id, proposal);
 
-        env::log_str(&(format!("Choicer membership created. info: {:#?}", member)));
+        match proposal.token_id {
+            None => {
+                self.total_locked -= proposal.funds;
+                refund(&proposal.owner, proposal.funds);
+            }
+            //tokens are returned by the same claim as rewards
+            Some(_) => self.credit(&proposal.owner, proposal.id, proposal.token_id.as_ref(), proposal.funds)
+        }
+        Event::ProposalCancelled(vec![ProposalCancelledData {
+            proposal_id: U64(proposal.id),
+            reason,
+            refunded: U128(proposal.funds)
+        }]).emit();
+        self.release_bond(proposal, slash_bond);
 
-        self.choicers.insert(&member_id, &member);
+        let mut involved: Vec<AccountId> = proposal.decisions.keys().collect();
+        involved.push(proposal.owner.clone());
+        for account_id in involved {
+            let mut choicer = self.choicers
+                .get(&account_id)
+                .expect(&(format!("No choicer with id @{}",account_id)));
+            choicer.current_choices -= 1;
+            self.choicers.insert(&account_id,&choicer);
+        }
+        self.assert_escrow();
+    }
+    //Contract must always hold all escrowed funds on top of Ⓝ staked for its storage
+    fn assert_escrow(&self) {
+        let storage_stake = env::storage_usage() as Balance * env::storage_byte_cost();
+        assert!(
+            env::account_balance() >= self.total_locked + storage_stake,
+            "Escrow invariant violated: contract balance {} is less than locked {} plus storage stake {}",
+            env::account_balance(), self.total_locked, storage_stake
+        );
     }
-    //view all proposals with "Open" status
-    fn is_active_proposal(&self, proposal_id: String) -> bool {
-        let status = self.proposals
+    //Proposal stage derived from the current block time
+    pub fn view_proposal_status(&self, proposal_id: ProposalId) -> ProposalStatus {
+        self.proposals
             .get(&proposal_id)
-            .expect(&(format!("No proposal with id {}",proposal_id)))
-            .status;
-        status == ProposalStatus::Open
+            .expect(&(format!("No proposal with id {}",&proposal_id)))
+            .current_status()
     }
-    pub fn view_active_proposals(
-        &self,
-    ) -> UnorderedMap<ProposalId,Proposal> {
-        let proposals = &self.proposals;
-        let mut active_proposals: UnorderedMap<String, Proposal> = UnorderedMap::new(b"active".to_vec());
-        for proposal in proposals.values() {
-            if self.is_active_proposal(proposal.id.clone()) == true {
-                active_proposals.insert(&proposal.id, &proposal);
-            }
+    pub fn view_proposal(&self, proposal_id: ProposalId) -> ProposalView {
+        let proposal = self.proposals
+            .get(&proposal_id)
+            .expect(&(format!("No proposal with id {}",&proposal_id)));
+        ProposalView {
+            decisions: Some(proposal.decisions.values().map(|decision| DecisionView::from(&decision)).collect()),
+            ..ProposalView::from(&proposal)
         }
-        active_proposals
-    } 
+    }
+    //Proposals by id order. Filters are applied first, `from_index` and `limit` page over matched proposals
+    pub fn view_proposals(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+        status_filter: Option<ProposalStatus>,
+        vote_type_filter: Option<VoteType>
+    ) -> Vec<ProposalView> {
+        self.find_proposals(from_index, limit, |proposal| {
+            status_filter.as_ref().map_or(true, |status| proposal.current_status() == *status)
+                && vote_type_filter.as_ref().map_or(true, |vote_type| proposal.vote_type == *vote_type)
+        })
+    }
+    pub fn view_proposals_by_owner(&self, owner: AccountId, from_index: Option<u64>, limit: Option<u64>) -> Vec<ProposalView> {
+        self.find_proposals(from_index, limit, |proposal| proposal.owner == owner)
+    }
+    //Proposals where account submitted a decision or voted
+    pub fn view_proposals_by_participant(&self, account: AccountId, from_index: Option<u64>, limit: Option<u64>) -> Vec<ProposalView> {
+        self.find_proposals(from_index, limit, |proposal| {
+            proposal.decisions.get(&account).is_some() || proposal.ballots.get(&account).is_some()
+        })
+    }
+
+//CHOICER SIDE
+    #[payable]
+    //Same as `storage_deposit` for caller: attached deposit pays for storage of choicer data
+    pub fn create_membership(&mut self) {
+        self.storage_deposit(None, None);
+    }
+    pub fn view_choicer(&self, account_id: AccountId) -> Option<ChoicerView> {
+        self.choicers.get(&account_id).map(|choicer| ChoicerView::from(&choicer))
+    }
     pub fn is_a_member(&self, id: AccountId) -> bool {
         match self.choicers.get(&id) {
             Some(_v) => true,
             None => false
         }
     }
-	//TODO: Add checking "is_proposal_owner?". Cause creator cannot vote/submit for their proposals
-    pub fn submit_decision(&mut self, proposal_id: String, metadata: String) {
+    //One decision per account. Creator cannot submit decisions for own proposal
+    pub fn submit_decision(&mut self, proposal_id: ProposalId, metadata: String, budget: Option<U128>) {
+        let budget = budget.map(|budget| budget.0);
+        let initial_storage = env::storage_usage();
         let predecessor = env::predecessor_account_id();
         assert!(self.is_a_member(predecessor.clone()),"You are not member. Create membership first for submit decisions");
         
         let mut proposal = self.proposals
             .get(&proposal_id)
             .expect(&(format!("No proposal with id {}",&proposal_id)));
-	    assert!(proposal.status == ProposalStatus::Open, "Proposal status is {:?} ", proposal.status);
+        let status = proposal.current_status();
+        assert!(
+            status == ProposalStatus::Open,
+            "Decisions are not accepted anymore: submission deadline passed at {}. Proposal status is {:?}",
+            proposal.proposal_deadline, status
+        );
+        assert!(proposal.owner != predecessor, "Proposal creator cannot submit decisions for own proposal");
+        assert!(
+            proposal.decisions.get(&predecessor).is_none(),
+            "You already submitted decision for proposal {}. Use edit_decision to change it", proposal_id
+        );
+        assert!(
+            proposal.decisions.len() < proposal.max_decisions as u64,
+            "Proposal {} already has max number of decisions: {}", proposal_id, proposal.max_decisions
+        );
+
+        assert_budget(&proposal, budget);
 
         let decision = Decision {
             performer : predecessor,
-            metadata
+            metadata,
+            budget
         };
 
         let member_id = env::predecessor_account_id();
@@ -304,189 +757,751 @@ impl Contract {
         choicer.current_choices += 1;
 
         self.choicers.insert(&member_id,&choicer);
-        env::log_str(&(format!(
-           "Choicer @{} info: {:#?} 
-            submitted decision {:?} for proposal {} ",
-            member_id, choicer, decision, proposal.title)));
+        Event::DecisionSubmitted(vec![DecisionData {
+            proposal_id: U64(proposal_id),
+            performer: decision.performer.clone(),
+            metadata: decision.metadata.clone(),
+            budget: decision.budget.map(U128)
+        }]).emit();
 
-        proposal.decisions.push(decision);
+        proposal.decisions.insert(&decision.performer, &decision);
 		
         self.proposals.insert(&proposal_id, &proposal);
+        self.charge_storage(&member_id, initial_storage);
     }
+    //Change own decision while proposal is open
+    pub fn edit_decision(&mut self, proposal_id: ProposalId, metadata: String, budget: Option<U128>) {
+        let budget = budget.map(|budget| budget.0);
+        let initial_storage = env::storage_usage();
+        let predecessor = env::predecessor_account_id();
+        let mut proposal = self.proposals
+            .get(&proposal_id)
+            .expect(&(format!("No proposal with id {}",&proposal_id)));
+        assert!(proposal.current_status() == ProposalStatus::Open, "Decisions can be changed only while proposal is Open");
+        assert_budget(&proposal, budget);
 
-    #[payable]
-    //send your ranged and ordering votes for decisions. TODO: checking for predecessor submitted decision in proposal
-    pub fn vote(&mut self, proposal_id: String, vote: HashMap<String, f64>) { 
+        let mut decision = proposal.decisions
+            .get(&predecessor)
+            .expect(&(format!("No decision from @{} for proposal {}", predecessor, proposal_id)));
+        decision.metadata = metadata.clone();
+        decision.budget = budget;
+
+        //number of decisions is the same, so proposal itself is not rewritten
+        proposal.decisions.insert(&predecessor, &decision);
+        self.charge_storage(&predecessor, initial_storage);
+        Event::DecisionEdited(vec![DecisionData {
+            proposal_id: U64(proposal_id),
+            performer: predecessor,
+            metadata,
+            budget: budget.map(U128)
+        }]).emit();
+    }
+    //Remove own decision while proposal is open
+    pub fn withdraw_decision(&mut self, proposal_id: ProposalId) {
+        let initial_storage = env::storage_usage();
+        let predecessor = env::predecessor_account_id();
+        let mut proposal = self.proposals
+            .get(&proposal_id)
+            .expect(&(format!("No proposal with id {}",&proposal_id)));
+        assert!(proposal.current_status() == ProposalStatus::Open, "Decisions can be withdrawn only while proposal is Open");
+
+        proposal.decisions
+            .remove(&predecessor)
+            .expect(&(format!("No decision from @{} for proposal {}", predecessor, proposal_id)));
+
+        let mut choicer = self.choicers
+            .get(&predecessor)
+            .expect(&(format!("No choicer with id @{}",predecessor)));
+        choicer.current_choices -= 1;
+        self.choicers.insert(&predecessor,&choicer);
+
+        self.proposals.insert(&proposal_id, &proposal);
+        self.charge_storage(&predecessor, initial_storage);
+        Event::DecisionWithdrawn(vec![DecisionWithdrawnData {
+            proposal_id: U64(proposal_id),
+            performer: predecessor
+        }]).emit();
+    }
+
+    //send your ranged and ordering votes for decisions. Only decision performers can vote
+    //  v1 ballot: {"account_1.near": 1.0, "account_2.near": 2.0, ...} where value is a place
+    pub fn vote(&mut self, proposal_id: ProposalId, vote: HashMap<String, f64>) { 
+        let proposal = self.proposals
+            .get(&proposal_id)
+            .expect(&(format!("No proposal with that id {}",&proposal_id)));
+        //membership, stage and ranking are checked by record_ballot, same as for v2 ballots
+        let tiers = validate_places(&proposal, &vote)
+            .and_then(|_| tiers_from_places(&vote))
+            .unwrap_or_else(|error| env::panic_str(&error.to_string()));
+        self.record_ballot(proposal_id, tiers);
+    }
+    //v2 ballot: accounts ordered from best to worst, optionally grouped into ties
+    pub fn vote_ranked(&mut self, proposal_id: ProposalId, ballot: RankedBallot) {
+        self.record_ballot(proposal_id, ballot.into_tiers());
+    }
+    fn record_ballot(&mut self, proposal_id: ProposalId, tiers: Vec<Vec<AccountId>>) {
+        let initial_storage = env::storage_usage();
         let member_id = env::predecessor_account_id();
         assert!(self.is_a_member(member_id.clone()),"You are not member. Create membership via same name function");
         
         let mut proposal = self.proposals
             .get(&proposal_id)
             .expect(&(format!("No proposal with that id {}",&proposal_id)));
-        assert!(proposal.status == ProposalStatus::Vote, "Election is not started. Now proposal is still open");
+        let status = proposal.current_status();
+        assert!(status != ProposalStatus::Open, "Election is not started. Now proposal is still open");
+        assert!(
+            status == ProposalStatus::Vote,
+            "Votes are not accepted anymore: vote deadline passed at {}", proposal.vote_deadline
+        );
+        let decisions = proposal.decision_list();
+        if let Err(error) = validate_ranking(&proposal, &decisions, &member_id, &tiers) {
+            env::panic_str(&error.to_string());
+        }
+        if proposal.status == ProposalStatus::Open {
+            Event::ElectionStarted(vec![ElectionStartedData {
+                proposal_id: U64(proposal_id),
+                candidates: decisions.iter().map(|decision| decision.performer.clone()).collect()
+            }]).emit();
+        }
+        proposal.status = status;
 
         let choice = Votes {
-            from: member_id,
-            vote
+            from: member_id.clone(),
+            ranking: index_tiers(&decisions, &tiers)
         };
 
-        proposal.vote_results.push(choice);
+        proposal.ballots.insert(&choice.from, &choice);
 
         self.proposals.insert(&proposal_id, &proposal);
+        self.charge_storage(&member_id, initial_storage);
+        Event::VoteCast(vec![VoteCastData {
+            proposal_id: U64(proposal_id),
+            voter: member_id,
+            ranking: tiers
+        }]).emit();
     }
     //see all votes from choicers before final counted. status: Vote
-    pub fn view_vote_board(&self, proposal_id: String ) -> Vec<Votes> { 
+    pub fn view_vote_board(&self, proposal_id: ProposalId ) -> Vec<VotesView> { 
         let proposal = self.proposals
             .get(&proposal_id)
             .expect(&(format!("No proposal with that title {}",&proposal_id)));
-        assert!(proposal.status == ProposalStatus::Vote, "Election not started. Now proposal is still open");
-        proposal.vote_results
+        assert!(proposal.current_status() != ProposalStatus::Open, "Election not started. Now proposal is still open");
+        let decisions = proposal.decision_list();
+        proposal.ballots
+            .values()
+            .map(|votes| VotesView {
+                from: votes.from.clone(),
+                ranking: votes.ranking
+                    .iter()
+                    .map(|group| group.iter().map(|index| decisions[*index as usize].performer.clone()).collect())
+                    .collect()
+            })
+            .collect()
     } 
-//---------------------------------------------------------
-    //VOTE ENGINE
-    // Calculate weights for vote table places. 
-    //  Depends on number of participants (p) and proposal funds for disperse
-    fn set_weights(&self, p: usize, funds: f64) -> Vec<f64> {
-        let p:i32 = p as i32;
-        let f:f64 = funds;
-	    
-        let aw:usize = (p-1) as usize;
-        let w_last = f/(p*(pow(2, aw) - 1)) as f64;
-		
-        let mut vec: Vec<f64> = Vec::with_capacity(aw);
-        let mut rev_vec: Vec<f64> = Vec::with_capacity(aw);
-		
-        // These are all done without reallocating...
-        for i in 0..aw {
-            vec.push(pow(2, i) as f64 * w_last.clone())
-        }	
-        for i in vec.iter().rev() {
-            rev_vec.push(*i)
-        }
-        rev_vec
-    }
-    //Accumulate all votes and calculate values for multisend proposal funds
-    fn calculate_vote_results(&mut self, proposal_id: String) -> HashMap<String, f64> {
-
+    //Pairwise preference matrix and Schulze strongest paths. Rows and columns follow `candidates`
+    pub fn view_pairwise_preferences(&self, proposal_id: ProposalId) -> PairwiseView {
         let proposal = self.proposals
             .get(&proposal_id)
-            .expect(&(format!("No proposal with id {}",proposal_id))); 
-        assert!(proposal.status == ProposalStatus::Payout, "Election not finished. Now choicers are still voting");
-       
-        let p = proposal.vote_results.len();
-        let funds = proposal.funds.clone();
-        let converted_funds = yton(funds) as f64;
-        //set weights for calculate
-        self.vote_engine.weights = self.set_weights(p, converted_funds);
-        
-        //Get vote results from contract
-        let votes = proposal.vote_results;
-        let mut results:Vec<HashMap<String, f64>> = Vec::new();
-        for i in votes.into_iter() {
-            results.push(i.vote)
-        }
-        //Convert every vote from vote results
-        for mut i in results.into_iter() {
-            //participant place in votes changes ( x => weights[x-1] )
-            for item in i.values_mut() {
-                *item = self.vote_engine.weights[(*item-1.0) as usize];
-            }
-            //push converted(weighted) results back
-            self.vote_engine.results.push(i)
+            .expect(&(format!("No proposal with id {}",&proposal_id)));
+        assert!(proposal.current_status() != ProposalStatus::Open, "Election not started. Now proposal is still open");
+
+        let decisions = proposal.decision_list();
+        let preferences = pairwise_preferences(decisions.len(), &proposal.ballots.values_as_vector().to_vec());
+        let strongest_paths = schulze_paths(&preferences);
+        PairwiseView {
+            candidates: decisions.iter().map(|decision| decision.performer.clone()).collect(),
+            winners: schulze_winners(&decisions, &preferences, proposal.winners),
+            preferences,
+            strongest_paths
         }
-        
-        //accumulate all results in one instruction for multisend
-        let v = self.vote_engine.results.clone();
-        let bomb = v.into_par_iter()
-            .fold(||HashMap::new(), |mut a: HashMap<String, f64>, b| {
-                a.extend(b);
-                a
-            }).reduce(||HashMap::new(),|mut a, b| {
-            for (k, v) in b {
-                if a.contains_key(&k) {
-                    let x = a.get(&k).unwrap();
-                    a.insert(k, v + x);
-                } else {
-                    a.insert(k, v);
-                }
-            }
-            a
-        });
-        bomb
     }
 
-    //Payout. Ⓝ mutisender based on vote results
-    fn payout(&mut self, proposal_id: String) {
+    //Count next `max_ballots` ballots after vote deadline. Anyone can call it until all ballots are counted,
+    //  then proposal can be settled. Returns true when counting is finished
+    pub fn tally_step(&mut self, proposal_id: ProposalId, max_ballots: u64) -> bool {
         let proposal = self.proposals
             .get(&proposal_id)
-            .expect(&(format!("No proposal with id {}",proposal_id))); 
-        
-        assert!(proposal.status == ProposalStatus::Payout, "Election not finished. Now choicers are still voting");
-
-        let votes = self.calculate_vote_results(proposal_id);
-        let predecessor = env::predecessor_account_id();
-        let deposit: Balance = proposal.funds;
+            .expect(&(format!("No proposal with id {}",proposal_id)));
+        assert!(!proposal.settled, "Proposal {} is already settled", proposal_id);
+        assert!(
+            proposal.current_status() == ProposalStatus::Payout,
+            "Ballots can be counted after vote deadline {}", proposal.vote_deadline
+        );
+        assert!(max_ballots > 0, "max_ballots must be greater than zero");
 
-        let mut total: f64 = 0.0;
-        //Check accounts from votes and calculate sending total
-        for account in votes.keys() {
-            assert!(
-                env::is_valid_account_id(predecessor.as_bytes()),
-                "Account @{} is invalid",
-                predecessor
-            );
+        let tally = self.count_ballots(&proposal, max_ballots);
+        self.tallies.insert(&proposal_id, &tally);
+        Event::TallyUpdated(vec![TallyUpdatedData {
+            proposal_id: U64(proposal_id),
+            counted: U64(tally.counted),
+            total: U64(proposal.ballots.len())
+        }]).emit();
+        tally.counted == proposal.ballots.len()
+    }
+    //Anyone can finalize proposal after vote deadline. Proposal pays out only once
+    pub fn settle(&mut self, proposal_id: ProposalId) {
+        let mut proposal = self.proposals
+            .get(&proposal_id)
+            .expect(&(format!("No proposal with id {}",proposal_id)));
 
-            let amount = votes[account].round();
-            total += amount;
+        assert!(!proposal.settled, "Proposal {} is already settled", proposal_id);
+        let now = env::block_timestamp();
+        //not enough decisions for election: no need to wait for the vote stage
+        if now >= proposal.proposal_deadline && (proposal.decisions.len() as usize) < MIN_DECISIONS {
+            self.close_without_payout(&mut proposal, "no_decisions", false);
+            return;
+        }
+        assert!(
+            now >= proposal.vote_deadline,
+            "Vote deadline is not passed yet. Proposal can be settled after {}", proposal.vote_deadline
+        );
+        //creator who left proposal unsettled after grace period loses bond
+        let abandoned = now >= proposal.vote_deadline + SETTLE_GRACE_PERIOD;
+        if (proposal.ballots.len() as usize) < MIN_VOTES {
+            self.close_without_payout(&mut proposal, "no_votes", abandoned);
+            return;
         }
 
-        env::log_str(format!("Sending {}Ⓝ", total).as_str());
-
-        let total_spending = total as Balance;
+        let remaining = proposal.ballots.len() - self.tallies.get(&proposal_id).map_or(0, |tally| tally.counted);
         assert!(
-            total_spending <= yton(deposit),
-            "Not enough attached tokens to run multisender (Supplied: {}Ⓝ. Demand: {}Ⓝ)",
-            yton(deposit),
-            total_spending
+            remaining <= SETTLE_TALLY_LIMIT,
+            "{} ballots are not counted yet. Call tally_step(proposal_id, max_ballots) before settle", remaining
         );
-        //Send Ⓝ proportionally vote results
-        for vote in votes {
-            let (account_id,amount) = vote;
-            let amount_u128:Balance = (amount.round()) as Balance;
+        let tally = self.count_ballots(&proposal, remaining);
 
-            env::log_str(format!("Sending ~{}Ⓝ to account @{}", amount_u128, account_id).as_str());
-            
-            let account_id:AccountId = account_id.parse().unwrap();
+        proposal.status = ProposalStatus::Payout;
+        proposal.settled = true;
+        self.proposals.insert(&proposal_id, &proposal);
+
+        self.payout(proposal_id, &tally);
+        self.release_bond(&proposal, abandoned);
+        self.assert_escrow();
+    }
+    //Return bond to creator, or slash it equally between decision performers.
+    //  Bond is slashed when creator cancels proposal with submitted decisions or abandons it
+    fn release_bond(&mut self, proposal: &Proposal, slash: bool) {
+        let bond = proposal.bond;
+        if bond == 0 {
+            return;
+        }
+        self.total_locked -= bond;
+
+        let slash = slash && !proposal.decisions.is_empty();
+        Event::BondReleased(vec![BondReleasedData {
+            proposal_id: U64(proposal.id),
+            owner: proposal.owner.clone(),
+            amount: U128(bond),
+            slashed: slash
+        }]).emit();
+        if slash {
+            let units: BTreeMap<String, u128> = proposal.decisions
+                .keys()
+                .map(|performer| (performer.to_string(), 1))
+                .collect();
+            let (shares, dust) = split_funds(bond, &units);
+            //slashed shares stay locked until performers claim them
+            for (account_id, amount) in shares {
+                let account_id: AccountId = account_id.parse().unwrap();
+                self.total_locked += amount;
+                self.credit(&account_id, proposal.id, None, amount);
+            }
+            refund(&proposal.owner, dust);
+        } else {
+            refund(&proposal.owner, bond);
+        }
+        self.assert_escrow();
+    }
+
+    //Payout. Ⓝ based on vote results are credited to claimable balances, nothing is transferred here.
+    //  Performers and creator take them with `claim` or `claim_for`
+    fn payout(&mut self, proposal_id: ProposalId, tally: &Tally) {
+        let proposal = self.proposals
+            .get(&proposal_id)
+            .expect(&(format!("No proposal with id {}",proposal_id))); 
+        
+        assert!(proposal.current_status() == ProposalStatus::Payout, "Election not finished. Now choicers are still voting");
+
+        let owner = proposal.owner.clone();
+        let deposit: Balance = proposal.funds;
+
+        let decisions = proposal.decision_list();
+        let (shares, dust) = vote_engine::allocate(
+            &decisions,
+            tally,
+            &proposal.scoring_rule,
+            proposal.winners,
+            deposit
+        );
+        //Every performer completes proposal, Ⓝ are credited proportionally vote results
+        for decision in decisions.iter() {
+            let account_id = decision.performer.clone();
+            let amount = shares.get(account_id.as_str()).cloned().unwrap_or(0);
             let mut choicer = self.choicers
                 .get(&account_id)
                 .expect(&(format!("No choicer with id @{}",account_id)));
 
             choicer.completed_choices += 1;
-            choicer.total_received += amount as Balance;
+            if proposal.token_id.is_none() {
+                choicer.total_received += amount;
+            }
             choicer.current_choices -= 1;
             
             self.choicers.insert(&account_id,&choicer);
-
-            Promise::new(account_id).transfer(amount_u128);
+            self.credit(&account_id, proposal_id, proposal.token_id.as_ref(), amount);
         }
+        //Unallocated funds and rounding remainder go back to the creator
+        self.credit(&owner, proposal_id, proposal.token_id.as_ref(), dust);
 
         let mut choicer = self.choicers
-                .get(&predecessor)
-                .expect(&(format!("No choicer with id @{}",predecessor)));
+                .get(&owner)
+                .expect(&(format!("No choicer with id @{}",owner)));
 
             choicer.completed_choices += 1;
             choicer.current_choices -= 1;
-            choicer.total_spending += total_spending;
-
-        self.choicers.insert(&predecessor,&choicer);
+            if proposal.token_id.is_none() {
+                choicer.total_spending += deposit - dust;
+            }
 
+        self.choicers.insert(&owner,&choicer);
+        self.tallies.remove(&proposal_id);
+        Event::ProposalSettled(vec![ProposalSettledData {
+            proposal_id: U64(proposal_id),
+            paid: U128(deposit - dust),
+            returned: U128(dust)
+        }]).emit();
     } 
+
+//CLAIMS
+    //Transfer all claimable Ⓝ of caller. Tokens are claimed by proposal with `claim_for`
+    pub fn claim(&mut self) -> Promise {
+        let account_id = env::predecessor_account_id();
+        let rewards = self.rewards.remove(&account_id).unwrap_or_default();
+        self.claimable.remove(&account_id);
+        self.send_rewards(account_id, None, rewards)
+    }
+    //Transfer claimable Ⓝ and tokens of caller from one proposal.
+    //  Token proposal can have both: rewards in token and Ⓝ share of slashed creator bond.
+    //  Both transfers with their `on_claim` callbacks run detached, joint promise can't be returned
+    pub fn claim_for(&mut self, proposal_id: ProposalId) {
+        let account_id = env::predecessor_account_id();
+        let token_id = self.proposals
+            .get(&proposal_id)
+            .expect(&(format!("No proposal with id {}",proposal_id)))
+            .token_id;
+        let near_reward = self.take_reward(&account_id, None, proposal_id);
+        let token_reward = match token_id.as_ref() {
+            Some(token_id) => self.take_reward(&account_id, Some(token_id), proposal_id),
+            None => None
+        };
+        assert!(
+            near_reward.is_some() || token_reward.is_some(),
+            "@{} has nothing to claim for proposal {}", account_id, proposal_id
+        );
+        if let Some(near_reward) = near_reward {
+            self.send_rewards(account_id.clone(), None, vec![near_reward]);
+        }
+        if let Some(token_reward) = token_reward {
+            self.send_rewards(account_id, token_id, vec![token_reward]);
+        }
+    }
+    //Failed claim transfer is credited back, so it can be claimed again
+    #[private]
+    pub fn on_claim(&mut self, account_id: AccountId, token_id: Option<AccountId>, rewards: Vec<(ProposalId, U128)>) {
+        let transferred = matches!(env::promise_result(0), PromiseResult::Successful(_));
+        let payouts: Vec<PayoutData> = rewards
+            .iter()
+            .map(|(proposal_id, amount)| PayoutData {
+                proposal_id: U64(*proposal_id),
+                receiver_id: account_id.clone(),
+                amount: *amount,
+                token_id: token_id.clone()
+            })
+            .collect();
+        if transferred {
+            Event::PayoutSent(payouts).emit();
+        } else {
+            let amounts: Vec<(ProposalId, Balance)> = rewards
+                .into_iter()
+                .map(|(proposal_id, amount)| (proposal_id, amount.0))
+                .collect();
+            if token_id.is_none() {
+                self.total_locked += amounts.iter().map(|(_, amount)| amount).sum::<Balance>();
+            }
+            self.credit_all(&account_id, token_id.as_ref(), &amounts);
+            Event::PayoutFailed(payouts).emit();
+        }
+        //no assert_escrow here: refund of failed transfer may not be received yet,
+        //  and panic in this callback would lose the re-credited rewards
+    }
+    //Ⓝ credited to account and not claimed yet
+    pub fn view_claimable(&self, account_id: AccountId) -> U128 {
+        U128(self.claimable.get(&account_id).unwrap_or(0))
+    }
+    pub fn view_rewards(&self, account_id: AccountId) -> Vec<(ProposalId, U128)> {
+        self.rewards
+            .get(&account_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(proposal_id, amount)| (proposal_id, U128(amount)))
+            .collect()
+    }
+    //Tokens credited to account by proposal. Token of every proposal is `token_id` of its view
+    pub fn view_token_rewards(&self, account_id: AccountId) -> Vec<(ProposalId, U128)> {
+        self.token_rewards
+            .get(&account_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(proposal_id, amount)| (proposal_id, U128(amount)))
+            .collect()
+    }
+}
+
+//Helpers with arguments not supported by near_bindgen
+impl Contract {
+    //Validate parameters and store new proposal of `owner`. Funds are in Ⓝ, or in NEP-141 `token_id`.
+    //  Only Ⓝ funds and bond are counted in total_locked
+    fn add_proposal(
+        &mut self,
+        owner: AccountId,
+        token_id: Option<AccountId>,
+        funds: Balance,
+        bond: Balance,
+        params: ProposalParams
+    ) -> ProposalId {
+        let ProposalParams {
+            vote_type,
+            title,
+            max_decisions,
+            proposal_duration,
+            vote_duration,
+            metadata,
+            scoring_rule,
+            winners
+        } = params;
+        assert!(
+            self.choicers.contains_key(&owner),
+            "You must create a membership first to push your proposals"
+        );
+        assert!(
+            title.len() <= MAX_TITLE_SIZE && metadata.len() <= MAX_METADATA_SIZE ,
+            "Too many symbols. Max title size is {} . Max description metadata size is {}", MAX_TITLE_SIZE, MAX_METADATA_SIZE
+        );
+        let scoring_rule = scoring_rule.unwrap_or(ScoringRule::Geometric);
+        let winners = winners.unwrap_or(1);
+        assert!(
+            winners > 0 && winners <= max_decisions,
+            "Number of winners must be from 1 to max_decisions"
+        );
+        assert!(
+            max_decisions as usize >= MIN_DECISIONS,
+            "Proposal needs at least {} decisions for election", MIN_DECISIONS
+        );
+        assert!(
+            max_decisions as usize <= MAX_DECISIONS,
+            "Proposal can have at most {} decisions", MAX_DECISIONS
+        );
+        assert!(
+            vote_type != VoteType::PerformerElection || max_decisions as usize <= MAX_PERFORMER_DECISIONS,
+            "PerformerElection can have at most {} decisions", MAX_PERFORMER_DECISIONS
+        );
+        scoring_rule.assert_valid(max_decisions);
+        assert!(
+            proposal_duration > 0 && vote_duration > 0,
+            "Proposal and vote durations must be greater than zero"
+        );
+
+        let initial_storage = env::storage_usage();
+        let start_time = env::block_timestamp();
+        let proposal_deadline = start_time + proposal_duration;
+        let vote_deadline = proposal_deadline + vote_duration;
+        self.last_proposal_id += 1;
+        let proposal_id = self.last_proposal_id;
+        let (decisions, ballots) = Proposal::new_collections(proposal_id);
+        let proposal = Proposal {
+            status : ProposalStatus::Open,
+            vote_type,
+            scoring_rule,
+            id : proposal_id,
+            title,
+            funds,
+            token_id,
+            bond,
+            owner : owner.clone(),
+            metadata,
+            max_decisions,
+            winners,
+            decisions,
+            ballots,
+            start_time,
+            proposal_deadline,
+            vote_deadline,
+            settled : false
+        };
+
+        Event::ProposalCreated(vec![ProposalCreatedData {
+            proposal_id: U64(proposal_id),
+            owner: proposal.owner.clone(),
+            title: proposal.title.clone(),
+            metadata: proposal.metadata.clone(),
+            vote_type: proposal.vote_type.clone(),
+            scoring_rule: proposal.scoring_rule.clone(),
+            funds: U128(proposal.funds),
+            token_id: proposal.token_id.clone(),
+            bond: U128(proposal.bond),
+            max_decisions: proposal.max_decisions,
+            winners: proposal.winners,
+            start_time: U64(proposal.start_time),
+            proposal_deadline: U64(proposal.proposal_deadline),
+            vote_deadline: U64(proposal.vote_deadline)
+        }]).emit();
+
+        let mut choicer = self.choicers
+                .get(&owner)
+                .expect(&(format!("No choicer with id @{}",owner)));
+
+        choicer.current_choices += 1;
+        choicer.proposals_created += 1;
+        self.choicers.insert(&owner,&choicer);
+
+        self.proposals.insert(&proposal_id, &proposal);
+        if proposal.token_id.is_none() {
+            self.total_locked += funds;
+        }
+        self.total_locked += bond;
+        self.charge_storage(&owner, initial_storage);
+        proposal_id
+    }
+    //Continue stored tally of proposal with next `max_ballots` ballots
+    fn count_ballots(&self, proposal: &Proposal, max_ballots: u64) -> Tally {
+        let mut tally = self.tallies
+            .get(&proposal.id)
+            .unwrap_or_else(|| Tally::new(&proposal.vote_type, proposal.decisions.len() as usize, &proposal.scoring_rule));
+        let end = proposal.ballots.len().min(tally.counted + max_ballots);
+        for index in tally.counted..end {
+            tally.add(&proposal.ballots.values_as_vector().get(index).unwrap());
+        }
+        tally
+    }
+    //Remove reward of proposal from claimable Ⓝ or tokens of account
+    fn take_reward(&mut self, account_id: &AccountId, token_id: Option<&AccountId>, proposal_id: ProposalId) -> Option<(ProposalId, Balance)> {
+        let rewards_by_account = if token_id.is_none() { &mut self.rewards } else { &mut self.token_rewards };
+        let mut rewards = rewards_by_account.get(account_id).unwrap_or_default();
+        let index = rewards
+            .iter()
+            .position(|(reward_proposal_id, _)| *reward_proposal_id == proposal_id)?;
+        let reward = rewards.remove(index);
+        if rewards.is_empty() {
+            rewards_by_account.remove(account_id);
+        } else {
+            rewards_by_account.insert(account_id, &rewards);
+        }
+
+        if token_id.is_none() {
+            let claimable = self.claimable.get(account_id).unwrap_or(0) - reward.1;
+            if claimable == 0 {
+                self.claimable.remove(account_id);
+            } else {
+                self.claimable.insert(account_id, &claimable);
+            }
+        }
+        Some(reward)
+    }
+    //Add Ⓝ or tokens of proposal to claimable balance. Ⓝ must be already counted in total_locked
+    fn credit(&mut self, account_id: &AccountId, proposal_id: ProposalId, token_id: Option<&AccountId>, amount: Balance) {
+        self.credit_all(account_id, token_id, &[(proposal_id, amount)]);
+    }
+    //Same for rewards of several proposals with one write, so failed claim of many proposals is cheap to restore
+    fn credit_all(&mut self, account_id: &AccountId, token_id: Option<&AccountId>, amounts: &[(ProposalId, Balance)]) {
+        let amounts: Vec<&(ProposalId, Balance)> = amounts.iter().filter(|(_, amount)| *amount > 0).collect();
+        if amounts.is_empty() {
+            return;
+        }
+        let rewards_by_account = if token_id.is_none() { &mut self.rewards } else { &mut self.token_rewards };
+        let mut rewards = rewards_by_account.get(account_id).unwrap_or_default();
+        for (proposal_id, amount) in amounts.iter() {
+            match rewards.iter_mut().find(|(reward_proposal_id, _)| reward_proposal_id == proposal_id) {
+                Some(reward) => reward.1 += amount,
+                None => rewards.push((*proposal_id, *amount))
+            }
+        }
+        rewards_by_account.insert(account_id, &rewards);
+        if token_id.is_none() {
+            let claimable = self.claimable.get(account_id).unwrap_or(0);
+            let total: Balance = amounts.iter().map(|(_, amount)| amount).sum();
+            self.claimable.insert(account_id, &(claimable + total));
+        }
+        Event::RewardCredited(amounts
+            .into_iter()
+            .map(|(proposal_id, amount)| PayoutData {
+                proposal_id: U64(*proposal_id),
+                receiver_id: account_id.clone(),
+                amount: U128(*amount),
+                token_id: token_id.cloned()
+            })
+            .collect()
+        ).emit();
+    }
+    //One transfer for all claimed rewards, resolved in `on_claim`. Tokens are sent with `ft_transfer`
+    fn send_rewards(&mut self, account_id: AccountId, token_id: Option<AccountId>, rewards: Vec<(ProposalId, Balance)>) -> Promise {
+        let amount: Balance = rewards.iter().map(|(_, amount)| amount).sum();
+        assert!(amount > 0, "@{} has nothing to claim", account_id);
+
+        let transfer = match &token_id {
+            None => {
+                self.total_locked -= amount;
+                Promise::new(account_id.clone()).transfer(amount)
+            }
+            //ft_transfer requires exactly 1 yoctoⓃ
+            Some(token_id) => ext_ft::ft_transfer(
+                account_id.clone(),
+                U128(amount),
+                None,
+                token_id.clone(),
+                1,
+                GAS_FOR_FT_TRANSFER
+            )
+        };
+        let rewards: Vec<(ProposalId, U128)> = rewards
+            .into_iter()
+            .map(|(proposal_id, amount)| (proposal_id, U128(amount)))
+            .collect();
+        self.assert_escrow();
+        //callback re-credits all rewards on failure, so it needs more gas for more proposals
+        let callback_gas = Gas(GAS_FOR_CLAIM_CALLBACK.0 + GAS_FOR_CLAIMED_REWARD.0 * rewards.len() as u64);
+        transfer
+            .then(ext_self::on_claim(
+                account_id,
+                token_id,
+                rewards,
+                env::current_account_id(),
+                0,
+                callback_gas
+            ))
+    }
+    fn find_proposals(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+        filter: impl Fn(&Proposal) -> bool
+    ) -> Vec<ProposalView> {
+        let limit = limit.unwrap_or(MAX_PAGE_LIMIT).min(MAX_PAGE_LIMIT);
+        (1..=self.last_proposal_id)
+            .filter_map(|proposal_id| self.proposals.get(&proposal_id))
+            .filter(|proposal| filter(proposal))
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit as usize)
+            .map(|proposal| ProposalView::from(&proposal))
+            .collect()
+    }
+}
+
+//v1 ballot places must be a strict permutation of 1..=n-1, where n is the number of decisions
+fn validate_places(proposal: &Proposal, vote: &HashMap<String, f64>) -> Result<(), BallotError> {
+    let places = proposal.decisions.len().saturating_sub(1) as f64;
+    let mut taken: HashSet<u64> = HashSet::new();
+    for (account, place) in vote.iter() {
+        if place.fract() != 0.0 || *place < 1.0 || *place > places {
+            return Err(BallotError::InvalidPlace(account.clone(), *place));
+        }
+        if !taken.insert(*place as u64) {
+            return Err(BallotError::DuplicatePlace(*place));
+        }
+    }
+    Ok(())
+}
+//Ranking must contain every other performer exactly once
+fn validate_ranking(
+    proposal: &Proposal,
+    decisions: &[Decision],
+    voter: &AccountId,
+    tiers: &[Vec<AccountId>]
+) -> Result<(), BallotError> {
+    if decisions.iter().all(|decision| &decision.performer != voter) {
+        return Err(BallotError::NotPerformer(voter.clone()));
+    }
+    if proposal.ballots.get(voter).is_some() {
+        return Err(BallotError::AlreadyVoted(voter.clone()));
+    }
+    let mut ranked: HashSet<&AccountId> = HashSet::new();
+    for group in tiers.iter() {
+        if group.is_empty() {
+            return Err(BallotError::EmptyTier);
+        }
+        for account in group.iter() {
+            if account == voter {
+                return Err(BallotError::SelfRanking(voter.clone()));
+            }
+            if decisions.iter().all(|decision| &decision.performer != account) {
+                return Err(BallotError::UnknownCandidate(account.to_string()));
+            }
+            if !ranked.insert(account) {
+                return Err(BallotError::DuplicateCandidate(account.clone()));
+            }
+        }
+    }
+    for decision in decisions.iter() {
+        if &decision.performer != voter && !ranked.contains(&decision.performer) {
+            return Err(BallotError::MissingCandidate(decision.performer.clone()));
+        }
+    }
+    Ok(())
+}
+//Convert v1 places into tiers from best to worst. Accounts with equal places are tied
+fn tiers_from_places(vote: &HashMap<String, f64>) -> Result<Vec<Vec<AccountId>>, BallotError> {
+    let mut places: Vec<(&String, f64)> = vote.iter().map(|(account, place)| (account, *place)).collect();
+    places.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap().then_with(|| a.0.cmp(b.0)));
+
+    let mut tiers: Vec<Vec<AccountId>> = Vec::new();
+    let mut last_place: Option<f64> = None;
+    for (account, place) in places {
+        let account_id: AccountId = account
+            .parse()
+            .map_err(|_| BallotError::UnknownCandidate(account.clone()))?;
+        if last_place == Some(place) {
+            tiers.last_mut().unwrap().push(account_id);
+        } else {
+            tiers.push(vec![account_id]);
+        }
+        last_place = Some(place);
+    }
+    Ok(tiers)
+}
+//Replace accounts with indexes of their decisions. Accounts without decisions are skipped
+fn index_tiers(decisions: &[Decision], tiers: &[Vec<AccountId>]) -> Vec<Vec<u16>> {
+    tiers
+        .iter()
+        .map(|group| group
+            .iter()
+            .filter_map(|account| decisions.iter().position(|decision| &decision.performer == account))
+            .map(|index| index as u16)
+            .collect::<Vec<u16>>())
+        .filter(|group| !group.is_empty())
+        .collect()
+}
+
+//Requested budget is a part of ProjectElection application only
+fn assert_budget(proposal: &Proposal, budget: Option<Balance>) {
+    if let Some(budget) = budget {
+        assert!(
+            matches!(proposal.vote_type, VoteType::ProjectElection),
+            "Requested budget can be set only for ProjectElection"
+        );
+        assert!(budget > 0, "Requested budget must be greater than zero");
+    }
+}
+
+//Send back unused part of attached deposit
+fn refund(account_id: &AccountId, amount: Balance) {
+    if amount > 0 {
+        Promise::new(account_id.clone()).transfer(amount);
+    }
 }
 
 //Converter helper
 fn yton(yocto_amount: Balance) -> Balance {
     yocto_amount  / 10u128.pow(24)
 }
+#[cfg(test)]
 fn ntoy(near_amount: Balance) -> Balance {
     near_amount * 10u128.pow(24)
 }
@@ -497,7 +1512,7 @@ mod tests {
 
     use super::*;
 	use near_sdk::{ AccountId, MockedBlockchain };
-    use near_sdk::{testing_env, VMContext, VMConfig, RuntimeFeesConfig};
+    use near_sdk::{testing_env, VMContext, VMConfig, RuntimeFeesConfig, PromiseOrValue};
 
     fn creator() -> String {
         "creator.near".to_string()
@@ -532,17 +1547,22 @@ mod tests {
         "alice.near".to_string()
     }
     
+    const DAY: Duration = 86_400_000_000_000;
+
     fn get_context(predecessor_account_id: String) -> VMContext {
+        get_context_at(predecessor_account_id, 0)
+    }
+    fn get_context_at(predecessor_account_id: String, block_timestamp: Timestamp) -> VMContext {
         VMContext {
             current_account_id: alice(),
             signer_account_id: bob(),
             signer_account_pk: vec![0, 1, 2],
-            attached_deposit: 1050_000_000_000_000_000_000_000_000, //1050Ⓝ 
+            attached_deposit: 1_050_000_000_000_000_000_000_000_000, //1050Ⓝ 
             predecessor_account_id,
             input: vec![],
             block_index: 0,
-            block_timestamp: 0,
-            account_balance: 0,
+            block_timestamp,
+            account_balance: 1_000_000_000_000_000_000_000_000_000, //1000Ⓝ already held by contract
             account_locked_balance: 0,
             storage_usage: 100000,
             prepaid_gas: 10u64.pow(18),
@@ -562,80 +1582,110 @@ mod tests {
         env::log_str(format!("Balance ~{} Ⓝ for account @{}", yton(env::account_balance()), creator()).as_str());
         let mut contract = Contract::new();
         contract.create_membership();
-        contract.create_proposal(
-            VoteType::ProjectElection,
-            "create logo".to_string(),
-            10_000_000_000_000_000_000_000_000, // 10Ⓝ
-            10,
-            "we need logo for us".to_string()
-        );
-        contract.change_funds("creator.near001".to_string(), 200.0); // 10Ⓝ -> 200Ⓝ
-        println!("{:?}", contract.view_active_proposals().to_vec());
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::ProjectElection,
+                title: "create logo".to_string(),
+                max_decisions: 10,
+                proposal_duration: 3 * DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(10_000_000_000_000_000_000_000_000) // 10Ⓝ
+        );
+        testing_env!(
+            get_context(creator())
+        );
+        contract.change_funds(proposal_id, U128(ntoy(200))); // 10Ⓝ -> 200Ⓝ
+        testing_env!(
+            get_context(creator())
+        );
+        //second proposal of the same creator must not overwrite the first one
+        let second_proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::PerformerElection,
+                title: "create banner".to_string(),
+                max_decisions: 10,
+                proposal_duration: 3 * DAY,
+                vote_duration: DAY,
+                metadata: "we need banner for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(10_000_000_000_000_000_000_000_000) // 10Ⓝ
+        );
+        assert_ne!(proposal_id, second_proposal_id);
+        assert_eq!(contract.proposals.get(&proposal_id).unwrap().funds, ntoy(200));
+        assert_eq!(contract.total_locked, ntoy(210) + 2 * CREATOR_BOND);
+        let open = contract.view_proposals(None, None, Some(ProposalStatus::Open), None);
+        assert_eq!(open.iter().map(|proposal| proposal.id).collect::<Vec<ProposalId>>(), vec![proposal_id, second_proposal_id]);
         //PARTICIPANT_1 CONTEXT. CREATE MEMBERSHIP AND SUBMIT DECISION
         testing_env!(
             get_context(participant_1())
         );
         contract.create_membership();
-        println!("{:?}", contract.is_active_proposal("creator.near001".to_string()));
+        assert_eq!(contract.view_proposal(proposal_id).status, ProposalStatus::Open);
         println!("{:?}", contract.is_a_member("participant_1.near".parse().unwrap()));
-        contract.submit_decision("creator.near001".to_string(), "metadadalink1".to_string());
+        contract.submit_decision(proposal_id, "metadadalink1".to_string(), None);
         //PARTICIPANT_2 CONTEXT. CREATE MEMBERSHIP AND SUBMIT DECISION
         testing_env!(
             get_context(participant_2())
         );
         contract.create_membership();
         println!("{:?}", contract.is_a_member("participant_2.near".parse().unwrap()));
-        contract.submit_decision("creator.near001".to_string(), "metadadalink2".to_string());
+        contract.submit_decision(proposal_id, "metadadalink2".to_string(), None);
         //PARTICIPANT_3 CONTEXT. CREATE MEMBERSHIP & SUBMIT DECISION
         testing_env!(
             get_context(participant_3())
         );
         contract.create_membership();
         println!("{:?}", contract.is_a_member("participant_3.near".parse().unwrap()));
-        contract.submit_decision("creator.near001".to_string(), "metadadalink3".to_string());
+        contract.submit_decision(proposal_id, "metadadalink3".to_string(), None);
         //PARTICIPANT_4 CONTEXT. CREATE MEMBERSHIP & SUBMIT DECISION
         testing_env!(
             get_context(participant_4())
         );
         contract.create_membership();
         println!("{:?}", contract.is_a_member("participant_4.near".parse().unwrap()));
-        contract.submit_decision("creator.near001".to_string(), "metadadalink4".to_string());
+        contract.submit_decision(proposal_id, "metadadalink4".to_string(), None);
         //PARTICIPANT_5 CONTEXT. CREATE MEMBERSHIP & SUBMIT DECISION
         testing_env!(
             get_context(participant_5())
         );
         contract.create_membership();
         println!("{:?}", contract.is_a_member("participant_5.near".parse().unwrap()));
-        contract.submit_decision("creator.near001".to_string(), "metadadalink5".to_string());
+        contract.submit_decision(proposal_id, "metadadalink5".to_string(), None);
         //PARTICIPANT_6 CONTEXT. CREATE MEMBERSHIP AND SUBMIT DECISION
         testing_env!(
             get_context(participant_6())
         );
         contract.create_membership();
-        println!("{:?}", contract.is_active_proposal("creator.near001".to_string()));
+        assert_eq!(contract.view_proposal(proposal_id).status, ProposalStatus::Open);
         println!("{:?}", contract.is_a_member("participant_6.near".parse().unwrap()));
-        contract.submit_decision("creator.near001".to_string(), "metadadalink6".to_string());
+        contract.submit_decision(proposal_id, "metadadalink6".to_string(), None);
         //PARTICIPANT_7 CONTEXT. CREATE MEMBERSHIP AND SUBMIT DECISION
         testing_env!(
             get_context(participant_7())
         );
         contract.create_membership();
-        println!("{:?}", contract.is_active_proposal("creator.near001".to_string()));
+        assert_eq!(contract.view_proposal(proposal_id).status, ProposalStatus::Open);
         println!("{:?}", contract.is_a_member("participant_7.near".parse().unwrap()));
-        contract.submit_decision("creator.near001".to_string(), "metadadalink7".to_string());
+        contract.submit_decision(proposal_id, "metadadalink7".to_string(), None);
 
-        //CREATOR CONTEXT. START ELECTION
+        //SUBMISSION DEADLINE PASSED. ELECTION STARTS
         testing_env!(
-            get_context(creator())
+            get_context_at(creator(), 3 * DAY)
         );
-        contract.start_election("creator.near001".to_string());
+        assert_eq!(contract.view_proposal_status(proposal_id), ProposalStatus::Vote);
 
         //PARTICIPANT_1 CONTEXT. VOTE
         testing_env!(
-            get_context(participant_1())
+            get_context_at(participant_1(), 3 * DAY)
         );
         contract.vote(
-            "creator.near001".to_string(),
+            proposal_id,
             HashMap::from([
                 ("participant_2.near".to_string(), 1.0),
                 ("participant_3.near".to_string(), 2.0),
@@ -647,10 +1697,10 @@ mod tests {
         );
         //PARTICIPANT_2 CONTEXT. VOTE
         testing_env!(
-            get_context(participant_2())
+            get_context_at(participant_2(), 3 * DAY)
         );
         contract.vote(
-            "creator.near001".to_string(),
+            proposal_id,
             HashMap::from([
                 ("participant_1.near".to_string(), 1.0),
                 ("participant_3.near".to_string(), 2.0),
@@ -662,10 +1712,10 @@ mod tests {
         );
         //PARTICIPANT_3 CONTEXT. VOTE
         testing_env!(
-            get_context(participant_3())
+            get_context_at(participant_3(), 3 * DAY)
         );
         contract.vote(
-            "creator.near001".to_string(),
+            proposal_id,
             HashMap::from([
                 ("participant_2.near".to_string(), 1.0),
                 ("participant_7.near".to_string(), 2.0),
@@ -677,10 +1727,10 @@ mod tests {
         );
         //PARTICIPANT_4 CONTEXT. VOTE
         testing_env!(
-            get_context(participant_4())
+            get_context_at(participant_4(), 3 * DAY)
         );
         contract.vote(
-            "creator.near001".to_string(),
+            proposal_id,
             HashMap::from([
                 ("participant_7.near".to_string(), 1.0),
                 ("participant_2.near".to_string(), 2.0),
@@ -692,10 +1742,10 @@ mod tests {
         );
         //PARTICIPANT_5 CONTEXT. VOTE
         testing_env!(
-            get_context(participant_5())
+            get_context_at(participant_5(), 3 * DAY)
         );
         contract.vote(
-            "creator.near001".to_string(),
+            proposal_id,
             HashMap::from([
                 ("participant_1.near".to_string(), 1.0),
                 ("participant_6.near".to_string(), 2.0),
@@ -707,10 +1757,10 @@ mod tests {
         );
         //PARTICIPANT_6 CONTEXT. VOTE
         testing_env!(
-            get_context(participant_6())
+            get_context_at(participant_6(), 3 * DAY)
         );
         contract.vote(
-            "creator.near001".to_string(),
+            proposal_id,
             HashMap::from([
                 ("participant_1.near".to_string(), 1.0),
                 ("participant_5.near".to_string(), 2.0),
@@ -722,10 +1772,10 @@ mod tests {
         ); 
         //PARTICIPANT_7 CONTEXT. VOTE
         testing_env!(
-            get_context(participant_7())
+            get_context_at(participant_7(), 3 * DAY)
         );
         contract.vote(
-            "creator.near001".to_string(),
+            proposal_id,
             HashMap::from([
                 ("participant_1.near".to_string(), 1.0),
                 ("participant_6.near".to_string(), 2.0),
@@ -735,15 +1785,18 @@ mod tests {
                 ("participant_2.near".to_string(), 6.0),
             ])
         );
-        //CREATOR CONTEXT. START PAYOUT
+        //VOTE DEADLINE PASSED. ANYONE CAN SETTLE PROPOSAL
         testing_env!(
-            get_context(creator())
+            get_context_at(bob(), 4 * DAY)
         );
-        println!("{:?}", contract.view_decisions("creator.near001".to_string()));
-        println!("{:?}", contract.view_vote_board("creator.near001".to_string()));
+        println!("{:?}", contract.view_decisions(proposal_id));
+        println!("{:?}", contract.view_vote_board(proposal_id));
 
-        contract.finish_election("creator.near001".to_string());
-        contract.payout("creator.near001".to_string());
+        assert_eq!(contract.view_proposal_status(proposal_id), ProposalStatus::Payout);
+        contract.settle(proposal_id);
+        assert!(contract.proposals.get(&proposal_id).unwrap().settled);
+        //settled funds stay locked until performers claim them
+        assert_eq!(contract.total_locked, ntoy(210) + CREATOR_BOND);
         
         println!("{:?}", contract.choicers.get(&"participant_1.near".parse().unwrap()));
         println!("{:?}", contract.choicers.get(&"participant_2.near".parse().unwrap()));
@@ -756,4 +1809,1468 @@ mod tests {
 
     }
 
+    #[test]
+    #[should_panic(expected = "Decisions are not accepted anymore")]
+    fn test_late_decision() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::PerformerElection,
+                title: "create logo".to_string(),
+                max_decisions: 10,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(10_000_000_000_000_000_000_000_000) // 10Ⓝ
+        );
+        testing_env!(
+            get_context_at(participant_1(), DAY)
+        );
+        contract.create_membership();
+        contract.submit_decision(proposal_id, "metadadalink1".to_string(), None);
+    }
+
+    #[test]
+    fn test_proposal_views() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        for (owner, vote_type, duration) in [
+            (creator(), VoteType::PerformerElection, DAY),
+            (bob(), VoteType::ProjectElection, DAY),
+            (creator(), VoteType::ProjectElection, 3 * DAY),
+        ] {
+            testing_env!(
+                get_context(owner)
+            );
+            contract.create_membership();
+            contract.create_proposal(
+                ProposalParams {
+                    vote_type,
+                    title: "create logo".to_string(),
+                    max_decisions: 10,
+                    proposal_duration: duration,
+                    vote_duration: DAY,
+                    metadata: "we need logo for us".to_string(),
+                    scoring_rule: None,
+                    winners: None
+                },
+                U128(ntoy(10))
+            );
+        }
+        testing_env!(
+            get_context(participant_1())
+        );
+        contract.create_membership();
+        contract.submit_decision(2, "metadadalink1".to_string(), None);
+
+        //views work without writing to storage
+        let mut context = get_context_at(bob(), DAY + DAY / 2);
+        context.is_view = true;
+        testing_env!(context);
+        let ids = |views: Vec<ProposalView>| views.iter().map(|view| view.id).collect::<Vec<ProposalId>>();
+        assert_eq!(ids(contract.view_proposals(None, None, None, None)), vec![1, 2, 3]);
+        assert_eq!(ids(contract.view_proposals(Some(1), Some(1), None, None)), vec![2]);
+        assert_eq!(ids(contract.view_proposals(None, None, Some(ProposalStatus::Vote), None)), vec![1, 2]);
+        assert_eq!(ids(contract.view_proposals(None, None, Some(ProposalStatus::Vote), Some(VoteType::ProjectElection))), vec![2]);
+        assert_eq!(ids(contract.view_proposals_by_owner(creator().parse().unwrap(), Some(1), None)), vec![3]);
+        assert_eq!(ids(contract.view_proposals_by_participant(participant_1().parse().unwrap(), None, None)), vec![2]);
+        assert_eq!(contract.view_proposals(None, None, None, None)[1].decisions, None);
+
+        let view = contract.view_proposal(2);
+        assert_eq!(view.status, ProposalStatus::Vote);
+        assert_eq!(view.decisions.as_ref().unwrap()[0].performer, participant_1().parse::<AccountId>().unwrap());
+        let json = near_sdk::serde_json::to_string(&view).unwrap();
+        assert!(json.contains("\"status\":{\"type\":\"Vote\"}"));
+        assert!(json.contains("\"funds\":\"10000000000000000000000000\""));
+        assert!(json.contains("\"proposal_deadline\":\"86400000000000\""));
+
+        let choicer = contract.view_choicer(bob().parse().unwrap()).unwrap();
+        assert_eq!(choicer.proposals_created, 1);
+        assert_eq!(choicer.total_spending, U128(0));
+        assert!(contract.view_choicer(alice().parse().unwrap()).is_none());
+    }
+
+    fn get_context_with_deposit(predecessor_account_id: String, attached_deposit: Balance) -> VMContext {
+        let mut context = get_context(predecessor_account_id);
+        context.attached_deposit = attached_deposit;
+        context
+    }
+
+    #[test]
+    fn test_storage_management() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::PerformerElection,
+                title: "create logo".to_string(),
+                max_decisions: 10,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(10))
+        );
+
+        testing_env!(
+            get_context_with_deposit(participant_1(), ntoy(1))
+        );
+        let balance = contract.storage_deposit(None, Some(true));
+        assert_eq!(balance.total, contract.storage_balance_bounds().min);
+        assert!(contract.is_a_member(participant_1().parse().unwrap()));
+        //registration bytes are already paid
+        assert!(balance.available.0 < balance.total.0);
+
+        contract.submit_decision(proposal_id, "metadadalink1".to_string(), None);
+        let after_submit = contract.storage_balance_of(participant_1().parse().unwrap()).unwrap();
+        assert!(after_submit.available.0 < balance.available.0);
+        //withdrawn decision frees paid bytes
+        contract.withdraw_decision(proposal_id);
+        assert_eq!(contract.storage_balance_of(participant_1().parse().unwrap()).unwrap(), balance);
+
+        testing_env!(
+            get_context_with_deposit(participant_1(), 1)
+        );
+        let balance = contract.storage_withdraw(None);
+        assert_eq!(balance.available.0, 0);
+        assert!(contract.storage_unregister(None));
+        assert!(contract.storage_balance_of(participant_1().parse().unwrap()).is_none());
+        assert!(!contract.is_a_member(participant_1().parse().unwrap()));
+        assert!(!contract.storage_unregister(None));
+    }
+
+    #[test]
+    #[should_panic(expected = "Force unregister is not supported")]
+    fn test_storage_unregister_force() {
+        testing_env!(
+            get_context(participant_1())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        testing_env!(
+            get_context_with_deposit(participant_1(), 1)
+        );
+        contract.storage_unregister(Some(true));
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough storage deposit")]
+    fn test_storage_is_paid_by_caller() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::PerformerElection,
+                title: "create logo".to_string(),
+                max_decisions: 10,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(10))
+        );
+        testing_env!(
+            get_context_with_deposit(participant_1(), ntoy(1))
+        );
+        contract.storage_deposit(None, Some(true));
+        testing_env!(
+            get_context_with_deposit(participant_1(), 1)
+        );
+        contract.storage_withdraw(None);
+        contract.submit_decision(proposal_id, "metadadalink1".to_string(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Vote deadline is not passed yet")]
+    fn test_early_settle() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::PerformerElection,
+                title: "create logo".to_string(),
+                max_decisions: 10,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(10_000_000_000_000_000_000_000_000) // 10Ⓝ
+        );
+        testing_env!(
+            get_context_at(bob(), DAY / 2)
+        );
+        contract.settle(proposal_id);
+    }
+
+    #[test]
+    fn test_migrate_string_ids() {
+        testing_env!(
+            get_context(alice())
+        );
+        let mut old = OldContract {
+            proposals: UnorderedMap::new(b"proposals".to_vec()),
+            choicers: LookupMap::new(b"choicers".to_vec()),
+            vote_engine: OldVoteEngine { weights: vec![8.0, 4.0, 2.0, 1.0], results: Vec::new() }
+        };
+        old.proposals.insert(&"creator.near001".to_string(), &OldProposal {
+            status: ProposalStatus::Open,
+            vote_type: VoteType::PerformerElection,
+            id: "creator.near001".to_string(),
+            title: "create logo".to_string(),
+            funds: ntoy(10),
+            owner: creator().parse().unwrap(),
+            metadata: "we need logo for us".to_string(),
+            max_decisions: 10,
+            decisions: [participant_1(), participant_2()]
+                .iter()
+                .map(|performer| OldDecision {
+                    performer: performer.parse().unwrap(),
+                    metadata: format!("logo from {}", performer)
+                })
+                .collect(),
+            vote_results: vec![OldVotes {
+                from: participant_1().parse().unwrap(),
+                vote: HashMap::from([(participant_2(), 1.0)])
+            }],
+            start_time: DAY
+        });
+        env::state_write(&old);
+
+        let contract = Contract::migrate();
+        assert_eq!(contract.last_proposal_id, 1);
+        assert_eq!(contract.total_locked, ntoy(10));
+        let proposal = contract.proposals.get(&1).unwrap();
+        assert_eq!(proposal.id, 1);
+        assert_eq!(proposal.funds, ntoy(10));
+        assert_eq!(proposal.proposal_deadline, DAY + MIGRATED_PROPOSAL_DURATION);
+        assert_eq!(proposal.vote_deadline, DAY + MIGRATED_PROPOSAL_DURATION + MIGRATED_VOTE_DURATION);
+        assert!(!proposal.settled);
+        assert!(contract.proposals.get(&2).is_none());
+
+        //embedded decisions and ballots are moved to proposal collections
+        let participant_2_id: AccountId = participant_2().parse().unwrap();
+        assert_eq!(proposal.decisions.len(), 2);
+        assert_eq!(proposal.decisions.get(&participant_2_id).unwrap().metadata, format!("logo from {}", participant_2()));
+        let ballot = proposal.ballots.get(&participant_1().parse().unwrap()).unwrap();
+        assert_eq!(ballot.ranking, vec![vec![1]]);
+    }
+
+    #[test]
+    fn test_migrate_invalid_ballots() {
+        testing_env!(
+            get_context(alice())
+        );
+        let mut old = OldContract {
+            proposals: UnorderedMap::new(b"proposals".to_vec()),
+            choicers: LookupMap::new(b"choicers".to_vec()),
+            vote_engine: OldVoteEngine { weights: Vec::new(), results: Vec::new() }
+        };
+        let old_decision = |performer: &String| OldDecision {
+            performer: performer.parse().unwrap(),
+            metadata: format!("logo from {}", performer)
+        };
+        let old_votes = |from: String, vote: &[(String, f64)]| OldVotes {
+            from: from.parse().unwrap(),
+            vote: vote.iter().cloned().collect()
+        };
+        //participant_1 submitted decision twice, only ballot of participant_1 is valid
+        old.proposals.insert(&"creator.near001".to_string(), &OldProposal {
+            status: ProposalStatus::Vote,
+            vote_type: VoteType::ProjectElection,
+            id: "creator.near001".to_string(),
+            title: "create logo".to_string(),
+            funds: ntoy(10),
+            owner: creator().parse().unwrap(),
+            metadata: "we need logo for us".to_string(),
+            max_decisions: 10,
+            decisions: [participant_1(), participant_2(), participant_1()].iter().map(old_decision).collect(),
+            vote_results: vec![
+                old_votes(participant_1(), &[(participant_2(), 1.0)]),
+                old_votes(bob(), &[(participant_1(), 1.0), (participant_2(), 2.0)]),
+                old_votes(participant_2(), &[(participant_2(), 1.0), (participant_1(), 2.0)])
+            ],
+            start_time: 0
+        });
+        //more decisions than PerformerElection can settle
+        let performers: Vec<String> = (0..=MAX_PERFORMER_DECISIONS).map(|i| format!("performer_{}.near", i)).collect();
+        old.proposals.insert(&"creator.near002".to_string(), &OldProposal {
+            status: ProposalStatus::Open,
+            vote_type: VoteType::PerformerElection,
+            id: "creator.near002".to_string(),
+            title: "create banner".to_string(),
+            funds: ntoy(20),
+            owner: creator().parse().unwrap(),
+            metadata: "we need banner for us".to_string(),
+            max_decisions: 1000,
+            decisions: performers.iter().map(old_decision).collect(),
+            vote_results: Vec::new(),
+            start_time: 0
+        });
+        let mut current_choices: Vec<(String, u16)> = vec![(creator(), 2), (participant_1(), 2), (participant_2(), 1)];
+        current_choices.extend(performers.iter().map(|performer| (performer.clone(), 1)));
+        for (account, choices) in current_choices {
+            old.choicers.insert(&account.parse().unwrap(), &Choicer {
+                account_id: account.parse().unwrap(),
+                total_received: 0,
+                completed_choices: 0,
+                current_choices: choices,
+                proposals_created: 0,
+                total_spending: 0
+            });
+        }
+        env::state_write(&old);
+
+        let mut contract = Contract::migrate();
+        let proposal = contract.proposals.get(&1).unwrap();
+        assert_eq!(proposal.decisions.len(), 2);
+        assert_eq!(proposal.ballots.len(), 1);
+        assert_eq!(contract.choicers.get(&participant_1().parse().unwrap()).unwrap().current_choices, 1);
+        let oversized = contract.proposals.get(&2).unwrap();
+        assert_eq!(oversized.current_status(), ProposalStatus::Cancelled);
+        assert_eq!(contract.total_locked, ntoy(10));
+
+        //migrated proposal can be settled and frees all participants
+        testing_env!(
+            get_context_at(bob(), proposal.vote_deadline)
+        );
+        contract.settle(1);
+        assert_eq!(contract.view_claimable(participant_2().parse().unwrap()), U128(ntoy(10)));
+        for account in [creator(), participant_1(), participant_2(), performers[0].clone()] {
+            assert_eq!(contract.choicers.get(&account.parse().unwrap()).unwrap().current_choices, 0);
+        }
+    }
+
+    #[test]
+    fn test_change_funds_escrow() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        testing_env!(
+            get_context(creator())
+        );
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::PerformerElection,
+                title: "create logo".to_string(),
+                max_decisions: 10,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+        assert_eq!(contract.total_locked, ntoy(100) + CREATOR_BOND);
+
+        let mut context = get_context(creator());
+        context.attached_deposit = 0;
+        testing_env!(context);
+        contract.change_funds(proposal_id, U128(ntoy(50)));
+        assert_eq!(contract.proposals.get(&proposal_id).unwrap().funds, ntoy(50));
+        assert_eq!(contract.total_locked, ntoy(50) + CREATOR_BOND);
+    }
+
+    #[test]
+    #[should_panic(expected = "Escrow invariant violated")]
+    fn test_escrow_counts_storage_stake() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        //balance with attached deposit covers funds and bond, but not Ⓝ staked for contract storage
+        let mut context = get_context(creator());
+        context.account_balance = 0;
+        context.attached_deposit = ntoy(100) + CREATOR_BOND;
+        testing_env!(context);
+        contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::PerformerElection,
+                title: "create logo".to_string(),
+                max_decisions: 10,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+    }
+
+    #[test]
+    fn test_cancel_and_no_quorum() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        testing_env!(
+            get_context(creator())
+        );
+        let cancelled_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::PerformerElection,
+                title: "create logo".to_string(),
+                max_decisions: 10,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+        testing_env!(
+            get_context(creator())
+        );
+        let abandoned_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::PerformerElection,
+                title: "create banner".to_string(),
+                max_decisions: 10,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need banner for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+        testing_env!(
+            get_context(participant_1())
+        );
+        contract.create_membership();
+        contract.submit_decision(abandoned_id, "metadadalink1".to_string(), None);
+
+        //no decisions yet - full refund
+        testing_env!(
+            get_context(creator())
+        );
+        contract.cancel_proposal(cancelled_id);
+        assert_eq!(contract.view_proposal_status(cancelled_id), ProposalStatus::Cancelled);
+        assert_eq!(contract.total_locked, ntoy(100) + CREATOR_BOND);
+
+        //single decision is not enough for election
+        testing_env!(
+            get_context_at(bob(), DAY)
+        );
+        contract.settle(abandoned_id);
+        assert_eq!(contract.view_proposal_status(abandoned_id), ProposalStatus::Cancelled);
+        assert_eq!(contract.total_locked, 0);
+        assert_eq!(contract.choicers.get(&creator().parse().unwrap()).unwrap().current_choices, 0);
+        assert_eq!(contract.choicers.get(&participant_1().parse().unwrap()).unwrap().current_choices, 0);
+    }
+
+    #[test]
+    fn test_abandoned_without_votes() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::PerformerElection,
+                title: "create logo".to_string(),
+                max_decisions: 10,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+        for participant in [participant_1(), participant_2()] {
+            testing_env!(
+                get_context(participant.clone())
+            );
+            contract.create_membership();
+            contract.submit_decision(proposal_id, participant, None);
+        }
+
+        //nobody voted and creator did not settle in time: funds are refunded, bond is slashed
+        testing_env!(
+            get_context_at(bob(), 2 * DAY + SETTLE_GRACE_PERIOD)
+        );
+        contract.settle(proposal_id);
+        assert_eq!(contract.view_proposal_status(proposal_id), ProposalStatus::Cancelled);
+        assert_eq!(contract.view_claimable(participant_1().parse().unwrap()), U128(CREATOR_BOND / 2));
+        assert_eq!(contract.view_claimable(participant_2().parse().unwrap()), U128(CREATOR_BOND / 2));
+        assert_eq!(contract.total_locked, CREATOR_BOND);
+    }
+
+    #[test]
+    fn test_decision_rules() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        testing_env!(
+            get_context(creator())
+        );
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::PerformerElection,
+                title: "create logo".to_string(),
+                max_decisions: 2,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+        for participant in [participant_1(), participant_2(), participant_3()] {
+            testing_env!(
+                get_context(participant)
+            );
+            contract.create_membership();
+        }
+        testing_env!(
+            get_context(participant_1())
+        );
+        contract.submit_decision(proposal_id, "metadadalink1".to_string(), None);
+        contract.edit_decision(proposal_id, "metadadalink1_v2".to_string(), None);
+        testing_env!(
+            get_context(participant_2())
+        );
+        contract.submit_decision(proposal_id, "metadadalink2".to_string(), None);
+        //max_decisions reached, participant_2 frees a place
+        contract.withdraw_decision(proposal_id);
+        testing_env!(
+            get_context(participant_3())
+        );
+        contract.submit_decision(proposal_id, "metadadalink3".to_string(), None);
+
+        let decisions = contract.view_decisions(proposal_id);
+        assert_eq!(decisions.len(), 2);
+        assert_eq!(decisions[0].metadata, "metadadalink1_v2".to_string());
+        assert_eq!(decisions[1].performer, participant_3().parse::<AccountId>().unwrap());
+        assert_eq!(contract.choicers.get(&participant_2().parse().unwrap()).unwrap().current_choices, 0);
+    }
+
+    #[test]
+    fn test_decision_budget() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::ProjectElection,
+                title: "grants".to_string(),
+                max_decisions: 2,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "fund our ecosystem".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+        testing_env!(
+            get_context(participant_1())
+        );
+        contract.create_membership();
+        contract.submit_decision(proposal_id, "metadadalink1".to_string(), Some(U128(ntoy(5))));
+        contract.edit_decision(proposal_id, "metadadalink1".to_string(), Some(U128(ntoy(7))));
+        assert_eq!(contract.view_decisions(proposal_id)[0].budget, Some(U128(ntoy(7))));
+    }
+
+    #[test]
+    #[should_panic(expected = "You already submitted decision")]
+    fn test_duplicate_decision() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        testing_env!(
+            get_context(creator())
+        );
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::PerformerElection,
+                title: "create logo".to_string(),
+                max_decisions: 10,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+        testing_env!(
+            get_context(participant_1())
+        );
+        contract.create_membership();
+        contract.submit_decision(proposal_id, "metadadalink1".to_string(), None);
+        contract.submit_decision(proposal_id, "metadadalink1".to_string(), None);
+    }
+
+    #[test]
+    fn test_ballot_validation() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        testing_env!(
+            get_context(creator())
+        );
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::PerformerElection,
+                title: "create logo".to_string(),
+                max_decisions: 10,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+        for participant in [participant_1(), participant_2(), participant_3()] {
+            testing_env!(
+                get_context(participant.clone())
+            );
+            contract.create_membership();
+            contract.submit_decision(proposal_id, participant, None);
+        }
+        let proposal = contract.proposals.get(&proposal_id).unwrap();
+        let voter: AccountId = participant_1().parse().unwrap();
+        let ballot = |places: &[(String, f64)]| places.iter().cloned().collect::<HashMap<String, f64>>();
+        let validate_ballot = |proposal: &Proposal, voter: &AccountId, vote: &HashMap<String, f64>| {
+            validate_places(proposal, vote)?;
+            validate_ranking(proposal, &proposal.decision_list(), voter, &tiers_from_places(vote)?)
+        };
+
+        assert_eq!(
+            validate_ballot(&proposal, &creator().parse().unwrap(), &ballot(&[])),
+            Err(BallotError::NotPerformer(creator().parse().unwrap()))
+        );
+        assert_eq!(
+            validate_ballot(&proposal, &voter, &ballot(&[(participant_1(), 1.0), (participant_2(), 2.0)])),
+            Err(BallotError::SelfRanking(voter.clone()))
+        );
+        assert_eq!(
+            validate_ballot(&proposal, &voter, &ballot(&[(participant_4(), 1.0)])),
+            Err(BallotError::UnknownCandidate(participant_4()))
+        );
+        assert_eq!(
+            validate_ballot(&proposal, &voter, &ballot(&[(participant_2(), 1.5)])),
+            Err(BallotError::InvalidPlace(participant_2(), 1.5))
+        );
+        assert_eq!(
+            validate_ballot(&proposal, &voter, &ballot(&[(participant_2(), 3.0)])),
+            Err(BallotError::InvalidPlace(participant_2(), 3.0))
+        );
+        assert_eq!(
+            validate_ballot(&proposal, &voter, &ballot(&[(participant_2(), 1.0), (participant_3(), 1.0)])),
+            Err(BallotError::DuplicatePlace(1.0))
+        );
+        assert_eq!(
+            validate_ballot(&proposal, &voter, &ballot(&[(participant_2(), 1.0)])),
+            Err(BallotError::MissingCandidate(participant_3().parse().unwrap()))
+        );
+        assert_eq!(
+            validate_ballot(&proposal, &voter, &ballot(&[(participant_2(), 2.0), (participant_3(), 1.0)])),
+            Ok(())
+        );
+
+        testing_env!(
+            get_context_at(participant_1(), DAY)
+        );
+        contract.vote(proposal_id, ballot(&[(participant_2(), 2.0), (participant_3(), 1.0)]));
+        let proposal = contract.proposals.get(&proposal_id).unwrap();
+        assert_eq!(
+            validate_ballot(&proposal, &voter, &ballot(&[(participant_2(), 2.0), (participant_3(), 1.0)])),
+            Err(BallotError::AlreadyVoted(voter))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Election is not started")]
+    fn test_vote_before_election() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::PerformerElection,
+                title: "create logo".to_string(),
+                max_decisions: 10,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+        for participant in [participant_1(), participant_2()] {
+            testing_env!(
+                get_context(participant.clone())
+            );
+            contract.create_membership();
+            contract.submit_decision(proposal_id, participant, None);
+        }
+        //valid ballot, but proposal is still open
+        contract.vote(proposal_id, HashMap::from([(participant_1(), 1.0)]));
+    }
+
+    #[test]
+    fn test_vote_ranked() {
+        let ranking: RankedBallot = serde_json::from_str(r#"["participant_2.near", "participant_4.near", "participant_3.near"]"#).unwrap();
+        let tiers: RankedBallot = serde_json::from_str(r#"[["participant_1.near"], ["participant_2.near", "participant_3.near"]]"#).unwrap();
+        assert_eq!(ranking.clone().into_tiers().len(), 3);
+        assert_eq!(tiers.clone().into_tiers()[1].len(), 2);
+
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        testing_env!(
+            get_context(creator())
+        );
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::PerformerElection,
+                title: "create logo".to_string(),
+                max_decisions: 10,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+        for participant in [participant_1(), participant_2(), participant_3(), participant_4()] {
+            testing_env!(
+                get_context(participant.clone())
+            );
+            contract.create_membership();
+            contract.submit_decision(proposal_id, participant, None);
+        }
+        testing_env!(
+            get_context_at(participant_1(), DAY)
+        );
+        contract.vote_ranked(proposal_id, ranking);
+        testing_env!(
+            get_context_at(participant_4(), DAY)
+        );
+        contract.vote_ranked(proposal_id, tiers);
+        //v1 clients are converted to the same ballot
+        testing_env!(
+            get_context_at(participant_2(), DAY)
+        );
+        contract.vote(proposal_id, HashMap::from([
+            (participant_4(), 1.0),
+            (participant_1(), 2.0),
+            (participant_3(), 3.0),
+        ]));
+
+        let board = contract.view_vote_board(proposal_id);
+        assert_eq!(board[1].ranking, vec![
+            vec![participant_1().parse().unwrap()],
+            vec![participant_2().parse().unwrap(), participant_3().parse().unwrap()]
+        ]);
+        assert_eq!(board[2].ranking[0], vec![participant_4().parse::<AccountId>().unwrap()]);
+        //tied decisions share the best place of their group
+        let proposal = contract.proposals.get(&proposal_id).unwrap();
+        assert_eq!(proposal.ballots.values_as_vector().get(1).unwrap().places(), vec![(0, 1), (1, 2), (2, 2)]);
+    }
+
+    //README example: 3 participants, 100Ⓝ, geometric weights give 4:3:2 units
+    fn readme_example(scoring_rule: ScoringRule) -> Vec<Balance> {
+        //start from empty storage on every call
+        env::set_blockchain_interface(MockedBlockchain::new(
+            get_context(creator()),
+            VMConfig::default(),
+            RuntimeFeesConfig::default(),
+            Vec::new(),
+            Default::default(),
+            Default::default(),
+            None
+        ));
+        let mut contract = Contract::new();
+        contract.create_membership();
+        testing_env!(
+            get_context(creator())
+        );
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::ProjectElection,
+                title: "create logo".to_string(),
+                max_decisions: 3,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for our project".to_string(),
+                scoring_rule: Some(scoring_rule),
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+        for participant in [participant_1(), participant_2(), participant_3()] {
+            testing_env!(
+                get_context(participant.clone())
+            );
+            contract.create_membership();
+            contract.submit_decision(proposal_id, format!("http://link_to_my_logo_for_you/from_{}", participant), None);
+        }
+        let ballots = [
+            (participant_1(), [(participant_2(), 1.0), (participant_3(), 2.0)]),
+            (participant_2(), [(participant_1(), 1.0), (participant_3(), 2.0)]),
+            (participant_3(), [(participant_1(), 1.0), (participant_2(), 2.0)]),
+        ];
+        for (voter, ballot) in ballots {
+            testing_env!(
+                get_context_at(voter, DAY)
+            );
+            contract.vote(proposal_id, HashMap::from(ballot));
+        }
+        testing_env!(
+            get_context_at(bob(), 2 * DAY)
+        );
+        contract.settle(proposal_id);
+
+        [participant_1(), participant_2(), participant_3()]
+            .iter()
+            .map(|account| contract.choicers.get(&account.parse().unwrap()).unwrap().total_received)
+            .collect()
+    }
+
+    #[test]
+    fn test_readme_example_geometric() {
+        assert_eq!(readme_example(ScoringRule::Geometric), vec![
+            44_444_444_444_444_444_444_444_444,
+            33_333_333_333_333_333_333_333_333,
+            22_222_222_222_222_222_222_222_222,
+        ]);
+    }
+
+    #[test]
+    fn test_scoring_rules() {
+        assert_eq!(ScoringRule::Geometric.weights(4), vec![8, 4, 2, 1]);
+        assert_eq!(ScoringRule::Borda.weights(4), vec![3, 2, 1, 0]);
+        assert_eq!(ScoringRule::Linear.weights(4), vec![4, 3, 2, 1]);
+        assert_eq!(ScoringRule::TopK { k: 2 }.weights(4), vec![2, 1, 0, 0]);
+
+        let units = BTreeMap::from([
+            (participant_1(), 4),
+            (participant_2(), 3),
+            (participant_3(), 2),
+        ]);
+        let funds = ntoy(100);
+        let (shares, dust) = ScoringRule::WinnerTakesMost { winner_share: 70 }.allocate(funds, &units, &BTreeMap::new());
+        assert_eq!(shares[&participant_1()], ntoy(70));
+        assert_eq!(shares[&participant_2()], 18_000_000_000_000_000_000_000_000);
+        assert_eq!(shares[&participant_3()], 12_000_000_000_000_000_000_000_000);
+        assert_eq!(dust, 0);
+
+        //every rule disperses exactly proposal funds
+        let rules = [
+            ScoringRule::Geometric,
+            ScoringRule::Borda,
+            ScoringRule::Linear,
+            ScoringRule::TopK { k: 1 },
+            ScoringRule::WinnerTakesMost { winner_share: 50 },
+        ];
+        for rule in rules {
+            let received: Balance = readme_example(rule.clone()).iter().sum();
+            assert!(received <= funds && funds - received < 10, "{:?} dispersed {}", rule, received);
+        }
+    }
+
+    #[test]
+    fn test_performer_election_winner() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        testing_env!(
+            get_context(creator())
+        );
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::PerformerElection,
+                title: "create logo".to_string(),
+                max_decisions: 3,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+        for participant in [participant_1(), participant_2(), participant_3()] {
+            testing_env!(
+                get_context(participant.clone())
+            );
+            contract.create_membership();
+            contract.submit_decision(proposal_id, participant, None);
+        }
+        let ballots = [
+            (participant_1(), [(participant_2(), 1.0), (participant_3(), 2.0)]),
+            (participant_2(), [(participant_1(), 1.0), (participant_3(), 2.0)]),
+            (participant_3(), [(participant_1(), 1.0), (participant_2(), 2.0)]),
+        ];
+        let event_names = || -> Vec<String> {
+            near_sdk::test_utils::get_logs()
+                .iter()
+                .map(|log| {
+                    let event: near_sdk::serde_json::Value = near_sdk::serde_json::from_str(log.strip_prefix("EVENT_JSON:").unwrap()).unwrap();
+                    assert_eq!(event["standard"], "choice");
+                    event["event"].as_str().unwrap().to_string()
+                })
+                .collect()
+        };
+        for (i, (voter, ballot)) in ballots.iter().cloned().enumerate() {
+            testing_env!(
+                get_context_at(voter, DAY)
+            );
+            contract.vote(proposal_id, HashMap::from(ballot));
+            //first ballot starts election
+            if i == 0 {
+                assert_eq!(event_names(), vec!["election_started", "vote_cast"]);
+            } else {
+                assert_eq!(event_names(), vec!["vote_cast"]);
+            }
+        }
+        testing_env!(
+            get_context_at(bob(), 2 * DAY)
+        );
+        let audit = contract.view_pairwise_preferences(proposal_id);
+        assert_eq!(audit.preferences, vec![vec![0, 1, 1], vec![0, 0, 1], vec![0, 0, 0]]);
+        assert_eq!(audit.winners, vec![participant_1().parse::<AccountId>().unwrap()]);
+
+        contract.settle(proposal_id);
+        assert_eq!(event_names(), vec!["reward_credited", "proposal_settled", "bond_released"]);
+        let received = |account: String| contract.choicers.get(&account.parse().unwrap()).unwrap();
+        assert_eq!(received(participant_1()).total_received, ntoy(100));
+        assert_eq!(received(participant_2()).total_received, 0);
+        assert_eq!(received(participant_2()).current_choices, 0);
+        assert_eq!(received(participant_2()).completed_choices, 1);
+    }
+
+    #[test]
+    fn test_claim_rewards() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::ProjectElection,
+                title: "create logo".to_string(),
+                max_decisions: 3,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+        for participant in [participant_1(), participant_2(), participant_3()] {
+            testing_env!(
+                get_context(participant.clone())
+            );
+            contract.create_membership();
+            contract.submit_decision(proposal_id, participant, None);
+        }
+        testing_env!(
+            get_context_at(participant_1(), DAY)
+        );
+        contract.vote(proposal_id, HashMap::from([(participant_2(), 1.0), (participant_3(), 2.0)]));
+        testing_env!(
+            get_context_at(bob(), 2 * DAY)
+        );
+        contract.settle(proposal_id);
+        //rewards are only credited, bond is returned at once
+        assert_eq!(contract.total_locked, ntoy(100));
+        let share = contract.view_claimable(participant_2().parse().unwrap());
+        assert_eq!(share, U128(66_666_666_666_666_666_666_666_666));
+        assert_eq!(contract.view_rewards(participant_2().parse().unwrap()), vec![(proposal_id, share)]);
+        assert_eq!(contract.choicers.get(&participant_2().parse().unwrap()).unwrap().total_received, share.0);
+        assert_eq!(contract.view_claimable(creator().parse().unwrap()), U128(1));
+
+        testing_env!(
+            get_context_with_deposit(participant_2(), 0)
+        );
+        contract.claim();
+        assert_eq!(contract.view_claimable(participant_2().parse().unwrap()), U128(0));
+        //callback gas grows with number of claimed proposals
+        let receipts = near_sdk::test_utils::get_created_receipts();
+        assert!(matches!(
+            &receipts[receipts.len() - 1].actions[0],
+            near_sdk::mock::VmAction::FunctionCall { method_name, gas, .. }
+                if method_name == "on_claim" && *gas == Gas(GAS_FOR_CLAIM_CALLBACK.0 + GAS_FOR_CLAIMED_REWARD.0)
+        ));
+        assert_eq!(contract.total_locked, ntoy(100) - share.0);
+        //receiver can't take transfer: reward is claimable again,
+        //  even before refund of the failed transfer is back on contract balance
+        let mut context = get_context_with_deposit(alice(), 0);
+        context.account_balance = 0;
+        testing_env!(
+            context,
+            VMConfig::default(),
+            RuntimeFeesConfig::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        contract.on_claim(participant_2().parse().unwrap(), None, vec![(proposal_id, share)]);
+        assert_eq!(contract.view_claimable(participant_2().parse().unwrap()), share);
+        assert_eq!(contract.total_locked, ntoy(100));
+
+        testing_env!(
+            get_context_with_deposit(participant_3(), 0)
+        );
+        contract.claim_for(proposal_id);
+        assert!(contract.view_rewards(participant_3().parse().unwrap()).is_empty());
+        assert_eq!(contract.total_locked, share.0 + 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token @fake.near is not accepted for prize pools")]
+    fn test_unknown_token() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        //any contract can pretend to be a token and name registered member as sender
+        testing_env!(
+            get_context("fake.near".to_string())
+        );
+        contract.ft_on_transfer(
+            creator().parse().unwrap(),
+            U128(1_000),
+            r#"{"action":"top_up","proposal_id":1}"#.to_string()
+        );
+    }
+
+    #[test]
+    fn test_token_prize_pool() {
+        let token = "usdc.near".to_string();
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        testing_env!(
+            get_context(alice())
+        );
+        contract.add_accepted_token(token.parse().unwrap());
+        assert_eq!(contract.view_accepted_tokens(), vec![token.parse::<AccountId>().unwrap()]);
+
+        //creator sends tokens with ft_transfer_call, token contract calls ft_on_transfer
+        testing_env!(
+            get_context(token.clone())
+        );
+        let msg = format!(
+            r#"{{"action":"create_proposal","vote_type":{{"type":"ProjectElection"}},"title":"create logo",
+            "max_decisions":3,"proposal_duration":{},"vote_duration":{},"metadata":"we need logo for us"}}"#,
+            DAY, DAY
+        );
+        match contract.ft_on_transfer(creator().parse().unwrap(), U128(1_000), msg) {
+            PromiseOrValue::Value(unused) => assert_eq!(unused, U128(0)),
+            PromiseOrValue::Promise(_) => panic!("All tokens must be used")
+        }
+        let proposal_id = contract.last_proposal_id;
+        contract.ft_on_transfer(creator().parse().unwrap(), U128(500), format!(r#"{{"action":"top_up","proposal_id":{}}}"#, proposal_id));
+        let view = contract.view_proposal(proposal_id);
+        assert_eq!(view.funds, U128(1_500));
+        assert_eq!(view.token_id, Some(token.parse().unwrap()));
+        //bond is taken in Ⓝ from storage deposit, tokens are not a part of Ⓝ escrow
+        assert_eq!(view.bond, U128(CREATOR_BOND));
+        assert_eq!(contract.total_locked, CREATOR_BOND);
+        assert_eq!(
+            contract.storage_balance_of(creator().parse().unwrap()).unwrap().total,
+            U128(ntoy(1050) - CREATOR_BOND)
+        );
+
+        for participant in [participant_1(), participant_2(), participant_3()] {
+            testing_env!(
+                get_context(participant.clone())
+            );
+            contract.create_membership();
+            contract.submit_decision(proposal_id, participant, None);
+        }
+        testing_env!(
+            get_context_at(participant_1(), DAY)
+        );
+        contract.vote(proposal_id, HashMap::from([(participant_2(), 1.0), (participant_3(), 2.0)]));
+        testing_env!(
+            get_context_at(bob(), 2 * DAY)
+        );
+        contract.settle(proposal_id);
+        assert_eq!(contract.view_token_rewards(participant_2().parse().unwrap()), vec![(proposal_id, U128(1_000))]);
+        assert_eq!(contract.view_token_rewards(participant_3().parse().unwrap()), vec![(proposal_id, U128(500))]);
+        assert_eq!(contract.view_claimable(participant_2().parse().unwrap()), U128(0));
+        assert_eq!(contract.total_locked, 0);
+
+        testing_env!(
+            get_context_with_deposit(participant_2(), 0)
+        );
+        contract.claim_for(proposal_id);
+        assert!(contract.view_token_rewards(participant_2().parse().unwrap()).is_empty());
+        let receipts = near_sdk::test_utils::get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, token.parse::<AccountId>().unwrap());
+        assert!(matches!(
+            &receipts[0].actions[0],
+            near_sdk::mock::VmAction::FunctionCall { method_name, deposit: 1, .. } if method_name == "ft_transfer"
+        ));
+        //receiver is not registered in token: tokens are claimable again
+        testing_env!(
+            get_context_with_deposit(alice(), 0),
+            VMConfig::default(),
+            RuntimeFeesConfig::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        contract.on_claim(participant_2().parse().unwrap(), Some(token.parse().unwrap()), vec![(proposal_id, U128(1_000))]);
+        assert_eq!(contract.view_token_rewards(participant_2().parse().unwrap()), vec![(proposal_id, U128(1_000))]);
+        assert_eq!(contract.total_locked, 0);
+    }
+
+    #[test]
+    fn test_claim_for_slashed_bond_of_token_proposal() {
+        let token = "usdc.near".to_string();
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        testing_env!(
+            get_context(alice())
+        );
+        contract.add_accepted_token(token.parse().unwrap());
+        testing_env!(
+            get_context(token.clone())
+        );
+        let msg = format!(
+            r#"{{"action":"create_proposal","vote_type":{{"type":"ProjectElection"}},"title":"create logo",
+            "max_decisions":3,"proposal_duration":{},"vote_duration":{},"metadata":"we need logo for us"}}"#,
+            DAY, DAY
+        );
+        contract.ft_on_transfer(creator().parse().unwrap(), U128(1_000), msg);
+        let proposal_id = contract.last_proposal_id;
+        testing_env!(
+            get_context(participant_1())
+        );
+        contract.create_membership();
+        contract.submit_decision(proposal_id, participant_1(), None);
+
+        //bond is slashed in Ⓝ, tokens are refunded to creator
+        testing_env!(
+            get_context_with_deposit(creator(), 0)
+        );
+        contract.cancel_proposal(proposal_id);
+        assert_eq!(contract.view_claimable(participant_1().parse().unwrap()), U128(CREATOR_BOND));
+        assert!(contract.view_token_rewards(participant_1().parse().unwrap()).is_empty());
+        assert_eq!(contract.view_token_rewards(creator().parse().unwrap()), vec![(proposal_id, U128(1_000))]);
+
+        testing_env!(
+            get_context_with_deposit(participant_1(), 0)
+        );
+        contract.claim_for(proposal_id);
+        assert_eq!(contract.view_claimable(participant_1().parse().unwrap()), U128(0));
+        assert_eq!(contract.total_locked, 0);
+        let receipts = near_sdk::test_utils::get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, participant_1().parse::<AccountId>().unwrap());
+        assert!(matches!(
+            &receipts[0].actions[0],
+            near_sdk::mock::VmAction::Transfer { deposit } if *deposit == CREATOR_BOND
+        ));
+    }
+
+    #[test]
+    fn test_claim_for_near_and_tokens() {
+        let token = "usdc.near".to_string();
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        testing_env!(
+            get_context(alice())
+        );
+        contract.add_accepted_token(token.parse().unwrap());
+        testing_env!(
+            get_context(token.clone())
+        );
+        let msg = format!(
+            r#"{{"action":"create_proposal","vote_type":{{"type":"ProjectElection"}},"title":"create logo",
+            "max_decisions":3,"proposal_duration":{},"vote_duration":{},"metadata":"we need logo for us"}}"#,
+            DAY, DAY
+        );
+        contract.ft_on_transfer(creator().parse().unwrap(), U128(1_000), msg);
+        let proposal_id = contract.last_proposal_id;
+        for participant in [participant_1(), participant_2()] {
+            testing_env!(
+                get_context(participant.clone())
+            );
+            contract.create_membership();
+            contract.submit_decision(proposal_id, participant, None);
+        }
+        testing_env!(
+            get_context_at(participant_1(), DAY)
+        );
+        contract.vote(proposal_id, HashMap::from([(participant_2(), 1.0)]));
+
+        //abandoned proposal: rewards are paid in tokens and bond is slashed in Ⓝ
+        testing_env!(
+            get_context_at(bob(), 2 * DAY + SETTLE_GRACE_PERIOD)
+        );
+        contract.settle(proposal_id);
+        let participant_2_id: AccountId = participant_2().parse().unwrap();
+        assert_eq!(contract.view_token_rewards(participant_2_id.clone()), vec![(proposal_id, U128(1_000))]);
+        assert_eq!(contract.view_claimable(participant_2_id.clone()), U128(CREATOR_BOND / 2));
+
+        testing_env!(
+            get_context_with_deposit(participant_2(), 0)
+        );
+        contract.claim_for(proposal_id);
+        assert!(contract.view_token_rewards(participant_2_id.clone()).is_empty());
+        assert_eq!(contract.view_claimable(participant_2_id.clone()), U128(0));
+        //two separate transfers, each with own callback
+        let receipts = near_sdk::test_utils::get_created_receipts();
+        assert!(receipts.iter().any(|receipt| receipt.receiver_id == participant_2_id && matches!(
+            &receipt.actions[0],
+            near_sdk::mock::VmAction::Transfer { deposit } if *deposit == CREATOR_BOND / 2
+        )));
+        assert!(receipts.iter().any(|receipt| receipt.receiver_id == token.parse::<AccountId>().unwrap() && matches!(
+            &receipt.actions[0],
+            near_sdk::mock::VmAction::FunctionCall { method_name, .. } if method_name == "ft_transfer"
+        )));
+        let callbacks = receipts
+            .iter()
+            .filter(|receipt| matches!(
+                &receipt.actions[0],
+                near_sdk::mock::VmAction::FunctionCall { method_name, .. } if method_name == "on_claim"
+            ))
+            .count();
+        assert_eq!(callbacks, 2);
+    }
+
+    //ProjectElection where every one of `n` performers ranks all others. Vote deadline is passed
+    fn many_ballots_proposal(n: u64) -> (Contract, ProposalId, Vec<String>) {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        let performers: Vec<String> = (0..n).map(|i| format!("performer_{}.near", i)).collect();
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::ProjectElection,
+                title: "fund projects".to_string(),
+                max_decisions: n as u16,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "projects for our DAO".to_string(),
+                scoring_rule: Some(ScoringRule::Linear),
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+        for performer in performers.iter() {
+            testing_env!(
+                get_context(performer.clone())
+            );
+            contract.create_membership();
+            contract.submit_decision(proposal_id, performer.clone(), None);
+        }
+        for performer in performers.iter() {
+            testing_env!(
+                get_context_at(performer.clone(), DAY)
+            );
+            let ranking: Vec<AccountId> = performers
+                .iter()
+                .filter(|account| *account != performer)
+                .map(|account| account.parse().unwrap())
+                .collect();
+            contract.vote_ranked(proposal_id, RankedBallot::Ranking(ranking));
+        }
+        testing_env!(
+            get_context_at(bob(), 2 * DAY)
+        );
+        (contract, proposal_id, performers)
+    }
+
+    #[test]
+    fn test_settle_many_ballots() {
+        let (mut contract, proposal_id, performers) = many_ballots_proposal(60);
+        assert!(performers.len() as u64 > SETTLE_TALLY_LIMIT);
+
+        assert!(!contract.tally_step(proposal_id, 20));
+        contract.settle(proposal_id);
+        assert!(contract.view_proposal(proposal_id).settled);
+        let credited: Balance = performers
+            .iter()
+            .chain([creator()].iter())
+            .map(|account| contract.view_claimable(account.parse().unwrap()).0)
+            .sum();
+        assert_eq!(credited, ntoy(100));
+    }
+
+    #[test]
+    #[should_panic(expected = "ballots are not counted yet")]
+    fn test_settle_needs_tally_step() {
+        let (mut contract, proposal_id, _) = many_ballots_proposal(SETTLE_TALLY_LIMIT + 1);
+        contract.settle(proposal_id);
+    }
+
+    #[test]
+    fn test_tally_step() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::ProjectElection,
+                title: "create logo".to_string(),
+                max_decisions: 3,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+        for participant in [participant_1(), participant_2(), participant_3()] {
+            testing_env!(
+                get_context(participant.clone())
+            );
+            contract.create_membership();
+            contract.submit_decision(proposal_id, participant, None);
+        }
+        let ballots = [
+            (participant_1(), [(participant_2(), 1.0), (participant_3(), 2.0)]),
+            (participant_2(), [(participant_1(), 1.0), (participant_3(), 2.0)]),
+            (participant_3(), [(participant_1(), 1.0), (participant_2(), 2.0)]),
+        ];
+        for (voter, ballot) in ballots.iter().cloned() {
+            testing_env!(
+                get_context_at(voter, DAY)
+            );
+            contract.vote(proposal_id, HashMap::from(ballot));
+        }
+        //ballots are counted across several calls
+        testing_env!(
+            get_context_at(bob(), 2 * DAY)
+        );
+        assert!(!contract.tally_step(proposal_id, 2));
+        assert_eq!(contract.tallies.get(&proposal_id).unwrap().counted, 2);
+        assert!(contract.tally_step(proposal_id, 2));
+        contract.settle(proposal_id);
+        assert!(contract.tallies.get(&proposal_id).is_none());
+
+        //weights 2:1 => participant_1 4 units, participant_2 3 units, participant_3 2 units
+        let claimable = |account: String| contract.view_claimable(account.parse().unwrap()).0;
+        assert_eq!(claimable(participant_1()), 44_444_444_444_444_444_444_444_444);
+        assert_eq!(claimable(participant_2()), 33_333_333_333_333_333_333_333_333);
+        assert_eq!(claimable(participant_3()), 22_222_222_222_222_222_222_222_222);
+    }
+
+    #[test]
+    fn test_payout_with_abstained_voters() {
+        testing_env!(
+            get_context(creator())
+        );
+        let mut contract = Contract::new();
+        contract.create_membership();
+        testing_env!(
+            get_context(creator())
+        );
+        let proposal_id = contract.create_proposal(
+            ProposalParams {
+                vote_type: VoteType::ProjectElection,
+                title: "create logo".to_string(),
+                max_decisions: 4,
+                proposal_duration: DAY,
+                vote_duration: DAY,
+                metadata: "we need logo for us".to_string(),
+                scoring_rule: None,
+                winners: None
+            },
+            U128(ntoy(100))
+        );
+        for participant in [participant_1(), participant_2(), participant_3(), participant_4()] {
+            testing_env!(
+                get_context(participant.clone())
+            );
+            contract.create_membership();
+            contract.submit_decision(proposal_id, participant, None);
+        }
+        //single ballot ranks 3 places while 3 other performers abstain
+        testing_env!(
+            get_context_at(participant_1(), DAY)
+        );
+        contract.vote(proposal_id, HashMap::from([
+            (participant_2(), 1.0),
+            (participant_3(), 2.0),
+            (participant_4(), 3.0),
+        ]));
+        testing_env!(
+            get_context_at(bob(), 2 * DAY)
+        );
+        contract.settle(proposal_id);
+
+        let received: Vec<Balance> = [participant_1(), participant_2(), participant_3(), participant_4()]
+            .iter()
+            .map(|account| contract.choicers.get(&account.parse().unwrap()).unwrap().total_received)
+            .collect();
+        //4:2:1 units
+        assert_eq!(received[0], 0);
+        assert_eq!(received[1], 57_142_857_142_857_142_857_142_857);
+        assert_eq!(received[2], 28_571_428_571_428_571_428_571_428);
+        assert_eq!(received[3], 14_285_714_285_714_285_714_285_714);
+        assert!(ntoy(100) - received.iter().sum::<Balance>() < 3);
+    }
 }
@@ -0,0 +1,191 @@
+//NEP-145 storage management. Registration in storage is choicer membership.
+//  Every call which writes proposals, decisions or ballots measures env::storage_usage() before and after
+//  and charges the caller for new bytes from own storage deposit. Freed bytes are returned to available balance.
+//  Settlement data is paid by contract: running tallies and claimable rewards written by
+//  `tally_step`, `settle` and `on_claim`. Anyone can settle and rewards are credited to accounts which
+//  may be unregistered, so charging them would let an empty storage deposit lock proposal funds forever.
+//  This data is temporary: tally is removed on settle and rewards are removed when claimed
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Serialize, Deserialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, StorageUsage};
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone, PartialEq)]
+pub struct StorageAccount {
+    total: Balance,                  //Ⓝ deposited by account
+    used: StorageUsage               //bytes written by account calls
+}
+impl StorageAccount {
+    fn available(&self) -> Balance {
+        self.total.saturating_sub(self.used as Balance * env::storage_byte_cost())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate="near_sdk::serde")]
+pub struct StorageBalance {
+    pub total: U128,
+    pub available: U128
+}
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate="near_sdk::serde")]
+pub struct StorageBalanceBounds {
+    pub min: U128,
+    pub max: Option<U128>
+}
+
+#[near_bindgen]
+impl Contract {
+    //Registers account as choicer or tops up its storage balance.
+    //  With registration_only extra deposit above minimum is refunded
+    #[payable]
+    pub fn storage_deposit(&mut self, account_id: Option<AccountId>, registration_only: Option<bool>) -> StorageBalance {
+        let deposit = env::attached_deposit();
+        let predecessor = env::predecessor_account_id();
+        let account_id = account_id.unwrap_or_else(|| predecessor.clone());
+        let registration_only = registration_only.unwrap_or(false);
+
+        if let Some(mut account) = self.storage_account(&account_id) {
+            if registration_only {
+                refund(&predecessor, deposit);
+            } else {
+                account.total += deposit;
+                self.storage_accounts.insert(&account_id, &account);
+            }
+            return self.storage_balance_of(account_id).unwrap();
+        }
+
+        assert!(
+            deposit >= RESERVED_FUNDS,
+            "You need attach at least {} yoctoⓃ (0.1Ⓝ) to register", RESERVED_FUNDS
+        );
+        let total = if registration_only {
+            refund(&predecessor, deposit - RESERVED_FUNDS);
+            RESERVED_FUNDS
+        } else {
+            deposit
+        };
+        let initial_storage = env::storage_usage();
+        self.storage_accounts.insert(&account_id, &StorageAccount { total, used: 0 });
+        self.choicers.insert(&account_id, &Choicer {
+            account_id: account_id.clone(),
+            total_received: 0,
+            completed_choices: 0,
+            current_choices: 0,
+            proposals_created: 0,
+            total_spending: 0
+        });
+        self.charge_storage(&account_id, initial_storage);
+        Event::MembershipCreated(vec![MembershipData { account_id: account_id.clone() }]).emit();
+
+        self.storage_balance_of(account_id).unwrap()
+    }
+    //Withdraw storage deposit which is not used by written data
+    #[payable]
+    pub fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut account = self.storage_account(&account_id)
+            .expect(&(format!("@{} is not registered", account_id)));
+        let available = account.available();
+        let amount = amount.map(|amount| amount.0).unwrap_or(available);
+        assert!(
+            amount <= available,
+            "Cannot withdraw {} yoctoⓃ, only {} yoctoⓃ available", amount, available
+        );
+
+        account.total -= amount;
+        self.storage_accounts.insert(&account_id, &account);
+        refund(&account_id, amount + 1);
+        self.storage_balance_of(account_id).unwrap()
+    }
+    //Remove membership and return storage deposit. Proposals, decisions and ballots written by account
+    //  stay in contract, so their bytes are kept from returned deposit.
+    //  Force is not supported: account with unfinished proposals or decisions cannot unregister
+    #[payable]
+    pub fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        assert!(
+            !force.unwrap_or(false),
+            "Force unregister is not supported. Proposals, decisions and ballots of account are kept until settlement"
+        );
+        let account_id = env::predecessor_account_id();
+        let account = match self.storage_account(&account_id) {
+            Some(account) => account,
+            None => return false
+        };
+        let choicer = self.choicers
+            .get(&account_id)
+            .expect(&(format!("No choicer with id @{}",account_id)));
+        assert!(
+            choicer.current_choices == 0,
+            "@{} has {} unfinished proposals or decisions and cannot unregister",
+            account_id, choicer.current_choices
+        );
+
+        let initial_storage = env::storage_usage();
+        self.choicers.remove(&account_id);
+        self.storage_accounts.remove(&account_id);
+        let freed = initial_storage - env::storage_usage();
+        let kept = account.used.saturating_sub(freed) as Balance * env::storage_byte_cost();
+        refund(&account_id, account.total.saturating_sub(kept) + 1);
+        true
+    }
+    pub fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: U128(RESERVED_FUNDS),
+            max: None
+        }
+    }
+    pub fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_account(&account_id).map(|account| StorageBalance {
+            total: U128(account.total),
+            available: U128(account.available())
+        })
+    }
+}
+
+impl Contract {
+    //Members created before storage management paid flat 0.1Ⓝ and have no storage record yet
+    pub(crate) fn storage_account(&self, account_id: &AccountId) -> Option<StorageAccount> {
+        self.storage_accounts.get(account_id).or_else(|| {
+            self.choicers
+                .get(account_id)
+                .map(|_| StorageAccount { total: RESERVED_FUNDS, used: 0 })
+        })
+    }
+    //Move Ⓝ from available storage deposit of account into contract escrow
+    pub(crate) fn take_storage_deposit(&mut self, account_id: &AccountId, amount: Balance) {
+        let mut account = self.storage_account(account_id)
+            .expect(&(format!("@{} is not registered. Call storage_deposit first", account_id)));
+        let available = account.available();
+        assert!(
+            available >= amount,
+            "@{} needs {} yoctoⓃ of available storage deposit, only {} yoctoⓃ available. Call storage_deposit",
+            account_id, amount, available
+        );
+        account.total -= amount;
+        self.storage_accounts.insert(account_id, &account);
+    }
+    //Charge account for storage written since `initial_storage`, or credit freed bytes back
+    pub(crate) fn charge_storage(&mut self, account_id: &AccountId, initial_storage: StorageUsage) {
+        let mut account = self.storage_account(account_id)
+            .expect(&(format!("@{} is not registered. Call storage_deposit first", account_id)));
+        let current_storage = env::storage_usage();
+        if current_storage >= initial_storage {
+            account.used += current_storage - initial_storage;
+        } else {
+            account.used = account.used.saturating_sub(initial_storage - current_storage);
+        }
+        let required = account.used as Balance * env::storage_byte_cost();
+        assert!(
+            account.total >= required,
+            "Not enough storage deposit for @{}: {} yoctoⓃ required, {} yoctoⓃ deposited. Call storage_deposit",
+            account_id, required, account.total
+        );
+        self.storage_accounts.insert(account_id, &account);
+    }
+}
+
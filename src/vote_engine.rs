@@ -0,0 +1,655 @@
+//Vote engine: decisions + ballots + scoring rule + funds => shares for every performer and remainder for creator.
+//  Ballots are added to a `Tally`, which contract stores between `tally_step` batches
+use std::collections::BTreeMap;
+#[cfg(feature = "simulate")]
+use rayon::prelude::*;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Serialize, Deserialize};
+use near_sdk::{AccountId, Balance};
+
+use crate::{Decision, Votes, VoteType};
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate="near_sdk::serde")]
+#[serde(tag="type")]
+//How places in ballots are turned into shares of ProjectElection funds. Chosen by creator for every proposal
+pub enum ScoringRule {
+    Geometric,                        //every next place weights twice less: [.., 4, 2, 1]
+    Borda,                            //place gets number of places below it: [m-1, .., 1, 0]. Single place gets [1]
+    Linear,                           //every next place weights one unit less: [m, .., 2, 1]
+    TopK { k: u16 },                  //linear weights for first k places, others get nothing
+    WinnerTakesMost { winner_share: u8 } //winner by linear score takes winner_share% of funds, others split the rest
+}
+impl ScoringRule {
+    //Checked on proposal creation, so the proposal can always be settled.
+    //  Every performer can vote and ranks max_decisions-1 places, units of all ballots must fit into u128
+    pub fn assert_valid(&self, max_decisions: u16) {
+        match self {
+            ScoringRule::TopK { k } => assert!(*k > 0, "TopK rule needs k > 0"),
+            ScoringRule::WinnerTakesMost { winner_share } => assert!(
+                *winner_share > 0 && *winner_share <= 100,
+                "Winner share must be from 1 to 100 percent"
+            ),
+            _ => {}
+        }
+        let places = (max_decisions as usize).saturating_sub(1);
+        let fits = match self {
+            ScoringRule::Geometric if places > 120 => false,
+            _ => self.weights(places).iter().sum::<u128>().checked_mul(max_decisions as u128).is_some()
+        };
+        assert!(fits, "{:?} scoring rule does not support {} decisions", self, max_decisions);
+    }
+    //Weight units for places 1..=m
+    pub fn weights(&self, m: usize) -> Vec<u128> {
+        match self {
+            ScoringRule::Geometric => {
+                assert!(m <= 128, "Too many places for geometric weights: {}", m);
+                (0..m).rev().map(|i| 1u128 << i).collect()
+            },
+            //with 2 decisions every ballot has one place, which must still score
+            ScoringRule::Borda if m == 1 => vec![1],
+            ScoringRule::Borda => (0..m).rev().map(|i| i as u128).collect(),
+            ScoringRule::Linear | ScoringRule::WinnerTakesMost { .. } => (1..=m).rev().map(|i| i as u128).collect(),
+            ScoringRule::TopK { k } => {
+                let k = (*k as usize).min(m);
+                (0..m).map(|place| if place < k { (k - place) as u128 } else { 0 }).collect()
+            }
+        }
+    }
+    //Split funds by accumulated units, nobody gets more than own cap.
+    //  Sum of shares plus returned remainder is exactly funds
+    pub fn allocate(
+        &self,
+        funds: Balance,
+        units: &BTreeMap<String, u128>,
+        caps: &BTreeMap<String, Balance>
+    ) -> (BTreeMap<String, Balance>, Balance) {
+        match self {
+            ScoringRule::WinnerTakesMost { winner_share } => {
+                //highest score wins, equal scores are resolved by account id
+                let winner = match units.iter().max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0))) {
+                    Some((winner, _)) => winner.clone(),
+                    None => return (BTreeMap::new(), funds),
+                };
+                let others: BTreeMap<String, u128> = units
+                    .iter()
+                    .filter(|(account, _)| **account != winner)
+                    .map(|(account, unit)| (account.clone(), *unit))
+                    .collect();
+                //nobody else scored: winner takes all
+                let mut prize = if others.values().sum::<u128>() == 0 {
+                    funds
+                } else {
+                    funds / 100 * (*winner_share as u128) + funds % 100 * (*winner_share as u128) / 100
+                };
+                if let Some(cap) = caps.get(&winner) {
+                    prize = prize.min(*cap);
+                }
+                let (mut shares, dust) = split_funds_capped(funds - prize, &others, caps);
+                shares.insert(winner, prize);
+                (shares, dust)
+            },
+            _ => split_funds_capped(funds, units, caps)
+        }
+    }
+}
+
+//Running tally of one proposal. Ballots are added in batches, so proposal with thousands of ballots
+//  can be counted in several transactions. Only data needed by proposal vote type is accumulated
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Tally {
+    pub counted: u64,                //number of ballots already added
+    vote_type: VoteType,
+    weights: Vec<u128>,              //ProjectElection: weights of places
+    units: Vec<u128>,                //ProjectElection: weight units by decision index
+    preferences: Vec<Vec<u32>>       //PerformerElection: pairwise preferences by decision index
+}
+impl Tally {
+    pub fn new(vote_type: &VoteType, decisions: usize, rule: &ScoringRule) -> Self {
+        match vote_type {
+            VoteType::PerformerElection => Tally {
+                counted: 0,
+                vote_type: VoteType::PerformerElection,
+                weights: Vec::new(),
+                units: Vec::new(),
+                preferences: vec![vec![0u32; decisions]; decisions]
+            },
+            VoteType::ProjectElection => Tally {
+                counted: 0,
+                vote_type: VoteType::ProjectElection,
+                weights: set_weights(decisions, rule),
+                units: vec![0u128; decisions],
+                preferences: Vec::new()
+            }
+        }
+    }
+    pub fn add(&mut self, ballot: &Votes) {
+        match self.vote_type {
+            VoteType::PerformerElection => add_preferences(&mut self.preferences, ballot),
+            VoteType::ProjectElection => {
+                for (index, place) in ballot.places() {
+                    self.units[index as usize] += self.weights[place-1];
+                }
+            }
+        }
+        self.counted += 1;
+    }
+}
+
+//Shares of funds for every performer and remainder, which goes back to creator.
+//  Sum of shares plus remainder is always exactly equal to funds
+pub fn allocate(
+    decisions: &[Decision],
+    tally: &Tally,
+    rule: &ScoringRule,
+    winners: u16,
+    funds: Balance
+) -> (BTreeMap<String, Balance>, Balance) {
+    match tally.vote_type {
+        //single or k winners by Schulze method split funds equally
+        VoteType::PerformerElection => {
+            let units: BTreeMap<String, u128> = schulze_winners(decisions, &tally.preferences, winners)
+                .into_iter()
+                .map(|account| (account.to_string(), 1))
+                .collect();
+            split_funds(funds, &units)
+        },
+        //projects get funds proportionally votes, but not more than requested budget
+        VoteType::ProjectElection => {
+            let units: BTreeMap<String, u128> = decisions
+                .iter()
+                .zip(tally.units.iter())
+                .filter(|(_, units)| **units > 0)
+                .map(|(decision, units)| (decision.performer.to_string(), *units))
+                .collect();
+            let caps: BTreeMap<String, Balance> = decisions
+                .iter()
+                .filter_map(|decision| decision.budget.map(|budget| (decision.performer.to_string(), budget)))
+                .collect();
+            rule.allocate(funds, &units, &caps)
+        }
+    }
+}
+
+// Calculate weights for vote table places in minimal units.
+//  Depends on number of participants (p = number of decisions) and proposal scoring rule.
+//  Every ballot ranks p-1 other performers
+pub fn set_weights(p: usize, rule: &ScoringRule) -> Vec<u128> {
+    assert!(p > 0, "No decisions to calculate");
+    rule.weights(p-1)
+}
+
+//Accumulate all votes and calculate weight units for every participant in one pass.
+//  Contract counts ballots in batches with `Tally`, this one is for off-chain recomputation and checks.
+//  Ballots are summed one by one into ordered map, so result doesn't depend on order of ballots
+#[cfg(any(test, feature = "simulate"))]
+pub fn tally(decisions: &[Decision], votes: &[Votes], rule: &ScoringRule) -> BTreeMap<String, u128> {
+    let weights = set_weights(decisions.len(), rule);
+    votes
+        .iter()
+        .map(|ballot| ballot_units(decisions, ballot, &weights))
+        .fold(BTreeMap::new(), merge_units)
+}
+
+//Same as `tally`, but ballots are converted and summed on all cores.
+//  Only for off-chain recomputation of large batches, wasm contract has no threads
+#[cfg(feature = "simulate")]
+pub fn tally_parallel(decisions: &[Decision], votes: &[Votes], rule: &ScoringRule) -> BTreeMap<String, u128> {
+    let weights = set_weights(decisions.len(), rule);
+    votes
+        .par_iter()
+        .map(|ballot| ballot_units(decisions, ballot, &weights))
+        .reduce(BTreeMap::new, merge_units)
+}
+
+//Sum units of two maps, same accounts are added up
+#[cfg(any(test, feature = "simulate"))]
+fn merge_units(mut a: BTreeMap<String, u128>, b: BTreeMap<String, u128>) -> BTreeMap<String, u128> {
+    for (account, unit) in b {
+        *a.entry(account).or_insert(0) += unit;
+    }
+    a
+}
+
+//Split funds proportionally to weight units. Shares are floored, so the sum of shares
+//  plus returned dust is always exactly equal to funds
+pub fn split_funds(funds: Balance, units: &BTreeMap<String, u128>) -> (BTreeMap<String, Balance>, Balance) {
+    let total_units: u128 = units.values().sum();
+    if total_units == 0 {
+        return (BTreeMap::new(), funds);
+    }
+
+    let mut shares: BTreeMap<String, Balance> = BTreeMap::new();
+    let mut sent: Balance = 0;
+    for (account, unit) in units.iter() {
+        let share = mul_div(funds, *unit, total_units);
+        sent += share;
+        shares.insert(account.clone(), share);
+    }
+    (shares, funds - sent)
+}
+
+//a * b / c rounded down. Product is calculated in 256 bits, so it never overflows.
+//  Result must fit into u128, it is always true for b <= c
+pub fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    assert!(c > 0, "Division by zero");
+    let (high, low) = mul_wide(a, b);
+    if high == 0 {
+        return low / c;
+    }
+    assert!(high < c, "{} * {} / {} overflows u128", a, b, c);
+    //long division of 256-bit product, remainder stays less than c
+    let mut remainder = high;
+    let mut quotient: u128 = 0;
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= c {
+            remainder = remainder.wrapping_sub(c);
+            quotient |= 1;
+        }
+    }
+    quotient
+}
+//Full 256-bit product as (high, low) 128-bit halves
+fn mul_wide(a: u128, b: u128) -> (u128, u128) {
+    let mask = u64::MAX as u128;
+    let (a_high, a_low) = (a >> 64, a & mask);
+    let (b_high, b_low) = (b >> 64, b & mask);
+    let low_low = a_low * b_low;
+    let high_low = a_high * b_low;
+    let low_high = a_low * b_high;
+    let high_high = a_high * b_high;
+    let middle = (low_low >> 64) + (high_low & mask) + (low_high & mask);
+    let low = (middle << 64) | (low_low & mask);
+    let high = high_high + (high_low >> 64) + (low_high >> 64) + (middle >> 64);
+    (high, low)
+}
+
+//Participant place in ballot changes into weight units ( x => weights[x-1] )
+#[cfg(any(test, feature = "simulate"))]
+pub fn ballot_units(decisions: &[Decision], votes: &Votes, weights: &[u128]) -> BTreeMap<String, u128> {
+    votes.places()
+        .into_iter()
+        .map(|(index, place)| (decisions[index as usize].performer.to_string(), weights[place-1]))
+        .collect()
+}
+
+//Proportional split where every share is limited by cap (water-filling).
+//  Shares above cap are fixed at cap and the excess is split again between others.
+//  Remainder (unallocated funds and dust) is returned
+pub fn split_funds_capped(
+    funds: Balance,
+    units: &BTreeMap<String, u128>,
+    caps: &BTreeMap<String, Balance>
+) -> (BTreeMap<String, Balance>, Balance) {
+    let mut shares: BTreeMap<String, Balance> = BTreeMap::new();
+    let mut active: BTreeMap<String, u128> = units.clone();
+    let mut remaining = funds;
+    while !active.is_empty() {
+        let (tentative, _) = split_funds(remaining, &active);
+        let capped: Vec<(String, Balance)> = tentative
+            .iter()
+            .filter_map(|(account, share)| match caps.get(account) {
+                Some(cap) if share >= cap => Some((account.clone(), *cap)),
+                _ => None
+            })
+            .collect();
+        if capped.is_empty() {
+            remaining -= tentative.values().sum::<Balance>();
+            shares.extend(tentative);
+            break;
+        }
+        for (account, cap) in capped {
+            remaining -= cap;
+            active.remove(&account);
+            shares.insert(account, cap);
+        }
+    }
+    (shares, remaining)
+}
+
+//Pairwise preferences: d[i][j] - number of ballots ranking decision i above decision j.
+//  Voter's own decision is unranked, so it is not compared in voter's ballot
+pub fn pairwise_preferences(n: usize, votes: &[Votes]) -> Vec<Vec<u32>> {
+    let mut d = vec![vec![0u32; n]; n];
+    for ballot in votes.iter() {
+        add_preferences(&mut d, ballot);
+    }
+    d
+}
+fn add_preferences(d: &mut [Vec<u32>], ballot: &Votes) {
+    let places = ballot.places();
+    for (i, place_i) in places.iter() {
+        for (j, place_j) in places.iter() {
+            if place_i < place_j {
+                d[*i as usize][*j as usize] += 1;
+            }
+        }
+    }
+}
+//Strongest paths of Schulze method (widest path via Floyd–Warshall)
+pub fn schulze_paths(d: &[Vec<u32>]) -> Vec<Vec<u32>> {
+    let n = d.len();
+    let mut p = vec![vec![0u32; n]; n];
+    for i in 0..n {
+        for j in 0..n {
+            if i != j && d[i][j] > d[j][i] {
+                p[i][j] = d[i][j];
+            }
+        }
+    }
+    for k in 0..n {
+        for i in 0..n {
+            if i == k {
+                continue;
+            }
+            for j in 0..n {
+                if j != i && j != k {
+                    p[i][j] = p[i][j].max(p[i][k].min(p[k][j]));
+                }
+            }
+        }
+    }
+    p
+}
+//Decision indexes ordered by number of Schulze wins. Equal results keep submission order
+pub fn schulze_ranking(p: &[Vec<u32>]) -> Vec<usize> {
+    let n = p.len();
+    let wins: Vec<usize> = (0..n)
+        .map(|i| (0..n).filter(|j| p[i][*j] > p[*j][i]).count())
+        .collect();
+    let mut ranking: Vec<usize> = (0..n).collect();
+    ranking.sort_by(|a, b| wins[*b].cmp(&wins[*a]).then_with(|| a.cmp(b)));
+    ranking
+}
+//First `winners` performers of Schulze ranking
+pub fn schulze_winners(decisions: &[Decision], d: &[Vec<u32>], winners: u16) -> Vec<AccountId> {
+    schulze_ranking(&schulze_paths(d))
+        .into_iter()
+        .take(winners as usize)
+        .map(|index| decisions[index].performer.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use crate::{ntoy, MIN_FUNDS};
+
+    fn participant_1() -> String {
+        "participant_1.near".to_string()
+    }
+    fn participant_2() -> String {
+        "participant_2.near".to_string()
+    }
+    fn participant_3() -> String {
+        "participant_3.near".to_string()
+    }
+
+    #[test]
+    fn test_split_funds_exact() {
+        let units = BTreeMap::from([
+            (participant_1(), 4),
+            (participant_2(), 3),
+            (participant_3(), 2),
+        ]);
+        let funds = ntoy(100);
+        let (shares, dust) = split_funds(funds, &units);
+
+        assert_eq!(shares[&participant_1()], 44_444_444_444_444_444_444_444_444);
+        assert_eq!(shares[&participant_2()], 33_333_333_333_333_333_333_333_333);
+        assert_eq!(shares[&participant_3()], 22_222_222_222_222_222_222_222_222);
+        assert_eq!(shares.values().sum::<Balance>() + dust, funds);
+        assert_eq!(dust, 1);
+    }
+
+    #[test]
+    fn test_split_funds_large_units() {
+        //geometric weights of many places: total units are far above 2^64
+        let units = BTreeMap::from([
+            (participant_1(), 4u128 << 100),
+            (participant_2(), 3u128 << 100),
+            (participant_3(), 2u128 << 100),
+        ]);
+        let funds = ntoy(100);
+        let (shares, dust) = split_funds(funds, &units);
+
+        assert_eq!(shares[&participant_1()], 44_444_444_444_444_444_444_444_444);
+        assert_eq!(shares.values().sum::<Balance>() + dust, funds);
+        assert_eq!(dust, 1);
+    }
+
+    #[test]
+    fn test_rule_limits() {
+        ScoringRule::Geometric.assert_valid(100);
+        ScoringRule::Linear.assert_valid(u16::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "Geometric scoring rule does not support 125 decisions")]
+    fn test_geometric_rule_limit() {
+        ScoringRule::Geometric.assert_valid(125);
+    }
+
+    #[test]
+    fn test_mul_div() {
+        assert_eq!(mul_div(10, 7, 9), 7);
+        assert_eq!(mul_div(u128::MAX, 3, 4), 255_211_775_190_703_847_597_530_955_573_826_158_591);
+        assert_eq!(mul_div(u128::MAX, u128::MAX - 1, u128::MAX), u128::MAX - 1);
+        assert_eq!(mul_div(ntoy(10_000), 7u128 << 100, 9u128 << 100), 7_777_777_777_777_777_777_777_777_777);
+    }
+
+    #[test]
+    fn test_tally_sums_repeated_accounts() {
+        let decisions: Vec<Decision> = [participant_1(), participant_2(), participant_3()]
+            .iter()
+            .map(|account| Decision { performer: account.parse().unwrap(), metadata: String::new(), budget: None })
+            .collect();
+        let ballot = |from: String, ranking: Vec<Vec<u16>>| Votes { from: from.parse().unwrap(), ranking };
+        let votes = vec![
+            ballot(participant_1(), vec![vec![1], vec![2]]),
+            ballot(participant_2(), vec![vec![0], vec![2]]),
+            ballot(participant_3(), vec![vec![1], vec![0]]),
+        ];
+        let units = tally(&decisions, &votes, &ScoringRule::Geometric);
+        //weights 2:1, participant_2 is first twice, participant_3 is second twice
+        assert_eq!(units[&participant_1()], 3);
+        assert_eq!(units[&participant_2()], 4);
+        assert_eq!(units[&participant_3()], 2);
+        #[cfg(feature = "simulate")]
+        assert_eq!(tally_parallel(&decisions, &votes, &ScoringRule::Geometric), units);
+    }
+
+    #[test]
+    fn test_borda_two_decisions() {
+        assert_eq!(ScoringRule::Borda.weights(1), vec![1]);
+        assert_eq!(ScoringRule::Borda.weights(3), vec![2, 1, 0]);
+        let decisions: Vec<Decision> = [participant_1(), participant_2()]
+            .iter()
+            .map(|account| Decision { performer: account.parse().unwrap(), metadata: String::new(), budget: None })
+            .collect();
+        let ballot = |from: String, ranking: Vec<Vec<u16>>| Votes { from: from.parse().unwrap(), ranking };
+        let votes = vec![
+            ballot(participant_1(), vec![vec![1]]),
+            ballot(participant_2(), vec![vec![0]]),
+        ];
+        //each performer is ranked first by the other one, funds are split equally
+        let units = tally(&decisions, &votes, &ScoringRule::Borda);
+        let (shares, remainder) = ScoringRule::Borda.allocate(ntoy(100), &units, &BTreeMap::new());
+        assert_eq!(shares[&participant_1()], ntoy(50));
+        assert_eq!(shares[&participant_2()], ntoy(50));
+        assert_eq!(remainder, 0);
+    }
+
+    #[test]
+    fn test_tally_in_batches() {
+        let decisions: Vec<Decision> = [participant_1(), participant_2(), participant_3()]
+            .iter()
+            .map(|account| Decision { performer: account.parse().unwrap(), metadata: String::new(), budget: None })
+            .collect();
+        let ballot = |from: String, ranking: Vec<Vec<u16>>| Votes { from: from.parse().unwrap(), ranking };
+        let votes = vec![
+            ballot(participant_1(), vec![vec![1], vec![2]]),
+            ballot(participant_2(), vec![vec![0], vec![2]]),
+            ballot(participant_3(), vec![vec![1, 0]]),
+        ];
+        for vote_type in [VoteType::PerformerElection, VoteType::ProjectElection] {
+            let mut batched = Tally::new(&vote_type, decisions.len(), &ScoringRule::Borda);
+            for batch in votes.chunks(2) {
+                let mut resumed = batched.clone();
+                batch.iter().for_each(|ballot| resumed.add(ballot));
+                batched = resumed;
+            }
+            assert_eq!(batched.counted, 3);
+            if vote_type == VoteType::PerformerElection {
+                assert_eq!(batched.preferences, pairwise_preferences(3, &votes));
+            } else {
+                let units = tally(&decisions, &votes, &ScoringRule::Borda);
+                assert_eq!(batched.units, vec![units[&participant_1()], units[&participant_2()], units[&participant_3()]]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_schulze_wikipedia_example() {
+        //A B C D E = 0 1 2 3 4
+        let groups: [(usize, [u16; 5]); 8] = [
+            (5, [0, 2, 1, 4, 3]),
+            (5, [0, 3, 4, 2, 1]),
+            (8, [1, 4, 3, 0, 2]),
+            (3, [2, 0, 1, 4, 3]),
+            (7, [2, 0, 4, 1, 3]),
+            (2, [2, 1, 0, 3, 4]),
+            (7, [3, 2, 4, 1, 0]),
+            (8, [4, 1, 0, 3, 2]),
+        ];
+        let mut votes: Vec<Votes> = Vec::new();
+        for (count, order) in groups.iter() {
+            for _ in 0..*count {
+                votes.push(Votes {
+                    from: "bob.near".parse().unwrap(),
+                    ranking: order.iter().map(|index| vec![*index]).collect()
+                });
+            }
+        }
+        let d = pairwise_preferences(5, &votes);
+        assert_eq!(d[0], vec![0, 20, 26, 30, 22]);
+        assert_eq!(d[4], vec![23, 27, 21, 31, 0]);
+        let p = schulze_paths(&d);
+        assert_eq!(p[0], vec![0, 28, 28, 30, 24]);
+        assert_eq!(p[4], vec![25, 28, 28, 31, 0]);
+        assert_eq!(schulze_ranking(&p), vec![4, 0, 2, 1, 3]);
+    }
+
+    #[test]
+    fn test_budget_allocation() {
+        let units = BTreeMap::from([
+            (participant_1(), 4),
+            (participant_2(), 3),
+            (participant_3(), 2),
+        ]);
+        let funds = ntoy(100);
+        //participant_1 asks less than proportional share, excess goes to others
+        let caps = BTreeMap::from([(participant_1(), ntoy(30))]);
+        let (shares, remainder) = split_funds_capped(funds, &units, &caps);
+        assert_eq!(shares[&participant_1()], ntoy(30));
+        assert_eq!(shares[&participant_2()], ntoy(42));
+        assert_eq!(shares[&participant_3()], ntoy(28));
+        assert_eq!(remainder, 0);
+        //everyone is capped, unallocated funds roll back to pool owner
+        let caps = BTreeMap::from([
+            (participant_1(), ntoy(30)),
+            (participant_2(), ntoy(30)),
+            (participant_3(), ntoy(10)),
+        ]);
+        let (shares, remainder) = ScoringRule::Geometric.allocate(funds, &units, &caps);
+        assert_eq!(shares.values().sum::<Balance>(), ntoy(70));
+        assert_eq!(remainder, ntoy(30));
+    }
+
+    fn scoring_rule_strategy() -> impl Strategy<Value = ScoringRule> {
+        prop_oneof![
+            Just(ScoringRule::Geometric),
+            Just(ScoringRule::Borda),
+            Just(ScoringRule::Linear),
+            (1u16..8).prop_map(|k| ScoringRule::TopK { k }),
+            (1u8..=100).prop_map(|winner_share| ScoringRule::WinnerTakesMost { winner_share }),
+        ]
+    }
+    //Performer of generated election: (abstains, order of all decisions, tie with next flags, budget)
+    type GeneratedPerformer = (bool, Vec<usize>, Vec<bool>, Option<Balance>);
+    //Decisions count and every performer
+    fn election_strategy() -> impl Strategy<Value = (usize, Vec<GeneratedPerformer>)> {
+        (2usize..10).prop_flat_map(|n| {
+            let performer = (
+                any::<bool>(),
+                Just((0..n).collect::<Vec<usize>>()).prop_shuffle(),
+                prop::collection::vec(any::<bool>(), n),
+                prop::option::of(1u128..ntoy(1_000_000))
+            );
+            (Just(n), prop::collection::vec(performer, n))
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn prop_funds_are_conserved(
+            (n, performers) in election_strategy(),
+            rule in scoring_rule_strategy(),
+            funds in MIN_FUNDS..ntoy(1_000_000),
+            with_budgets in any::<bool>()
+        ) {
+            let accounts: Vec<String> = (0..n).map(|i| format!("participant_{}.near", i)).collect();
+            let decisions: Vec<Decision> = performers
+                .iter()
+                .enumerate()
+                .map(|(i, (_, _, _, budget))| Decision {
+                    performer: accounts[i].parse().unwrap(),
+                    metadata: String::new(),
+                    budget: if with_budgets { *budget } else { None }
+                })
+                .collect();
+            //voters rank every other performer, some of them abstain
+            let mut votes: Vec<Votes> = Vec::new();
+            for (voter, (abstains, order, ties, _)) in performers.iter().enumerate() {
+                if *abstains {
+                    continue;
+                }
+                let mut ranking: Vec<Vec<u16>> = Vec::new();
+                let mut tie_with_previous = false;
+                for (position, index) in order.iter().filter(|index| **index != voter).enumerate() {
+                    if tie_with_previous {
+                        ranking.last_mut().unwrap().push(*index as u16);
+                    } else {
+                        ranking.push(vec![*index as u16]);
+                    }
+                    tie_with_previous = ties[position];
+                }
+                votes.push(Votes { from: accounts[voter].parse().unwrap(), ranking });
+            }
+
+            prop_assert_eq!(rule.weights(n - 1).len(), n - 1);
+            let units = tally(&decisions, &votes, &rule);
+            let caps: BTreeMap<String, Balance> = decisions
+                .iter()
+                .filter_map(|decision| decision.budget.map(|budget| (decision.performer.to_string(), budget)))
+                .collect();
+
+            let (shares, remainder) = rule.allocate(funds, &units, &caps);
+            prop_assert_eq!(shares.values().sum::<Balance>() + remainder, funds);
+            for (account, share) in shares.iter() {
+                if let Some(cap) = caps.get(account) {
+                    prop_assert!(share <= cap);
+                }
+            }
+            //every ballot scores its first place, so without budgets only rounding dust can be left
+            if caps.is_empty() && !votes.is_empty() {
+                prop_assert!(remainder < n as u128 + 1);
+            }
+        }
+    }
+}